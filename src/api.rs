@@ -0,0 +1,295 @@
+use {
+    std::{
+        str,
+        sync::Arc,
+        time::Duration,
+    },
+
+    tokio::{
+        io::{
+            AsyncReadExt,
+            AsyncWriteExt,
+        },
+        net::{
+            TcpListener,
+            TcpStream,
+        },
+        sync::{
+            mpsc::UnboundedSender,
+            oneshot,
+            Semaphore,
+        },
+        time::timeout,
+    },
+    serde_json::{
+        json,
+        Value,
+    },
+    anyhow::{
+        Result,
+        Context,
+    },
+    log::debug,
+
+    crate::config::{
+        Api,
+        Stream,
+    },
+};
+
+
+/// Maximum size of the request header and body
+const MAX_REQUEST_SIZE: usize = 64 * 1024;
+/// Time to receive complete request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Maximum number of the concurrent connections
+const MAX_CONNECTIONS: usize = 64;
+
+
+/// Request to the application from the HTTP API.
+#[derive(Debug)]
+pub enum Command {
+    /// List of the streams with state
+    List,
+    /// Statistics of all streams
+    Stats,
+    /// Statistics of all streams in Prometheus text format
+    Metrics,
+    /// Stream definition, state and statistics
+    Get(String),
+    Start(String),
+    Stop(String),
+    Restart(String),
+    /// Reloads stream definition from the configuration file and restarts stream
+    Reload(String),
+    /// Adds and starts new stream
+    Add(Box<Stream>),
+}
+
+
+/// HTTP response.
+#[derive(Debug)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// Returns response with JSON body.
+    pub fn json(status: u16, body: &Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            body: serde_json::to_vec_pretty(body).unwrap_or_default(),
+        }
+    }
+
+    #[inline]
+    pub fn ok(body: Value) -> Self {
+        Self::json(200, &body)
+    }
+
+    pub fn error<S: ToString>(status: u16, message: S) -> Self {
+        Self::json(status, &json!({ "error": message.to_string() }))
+    }
+
+    /// Returns response with plain text body.
+    pub fn text(body: String) -> Self {
+        Self {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            body: body.into_bytes(),
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            201 => "Created",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            408 => "Request Timeout",
+            409 => "Conflict",
+            413 => "Payload Too Large",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+
+/// Command with channel for the response.
+pub type ApiRequest = (Command, oneshot::Sender<Response>);
+
+
+/// Returns command for the request method, path and body.
+fn route(method: &str, path: &str, body: &[u8]) -> Result<Command, Response> {
+    let path = path.split('?').next().unwrap_or_default();
+    let parts: Vec<&str> = path.split('/').filter(|s| ! s.is_empty()).collect();
+
+    let command = match (method, parts.as_slice()) {
+        ("GET", ["streams"]) => Command::List,
+        ("GET", ["stats"]) => Command::Stats,
+        ("GET", ["metrics"]) => Command::Metrics,
+        ("GET", ["streams", name]) => Command::Get(name.to_string()),
+        ("POST", ["streams"]) => {
+            let stream: Stream = serde_json::from_slice(body)
+                .map_err(|err| Response::error(400, format!("Invalid stream definition: {}", err)))?;
+            Command::Add(Box::new(stream))
+        }
+        ("POST", ["streams", name, action]) => {
+            let name = name.to_string();
+            match *action {
+                "start" => Command::Start(name),
+                "stop" => Command::Stop(name),
+                "restart" => Command::Restart(name),
+                "reload" => Command::Reload(name),
+                _ => return Err(Response::error(404, "Not found")),
+            }
+        }
+        (_, ["streams"]) | (_, ["stats"]) | (_, ["metrics"]) | (_, ["streams", _]) | (_, ["streams", _, _]) => {
+            return Err(Response::error(405, "Method not allowed"))
+        }
+        _ => return Err(Response::error(404, "Not found")),
+    };
+
+    Ok(command)
+}
+
+
+/// Reads HTTP request. Returns method, path and body.
+async fn read_request(socket: &mut TcpStream) -> Result<(String, String, Vec<u8>), Response> {
+    let mut buf = Vec::new();
+    let mut chunk = [0; 4096];
+
+    let header_size = loop {
+        if let Some(pos) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            break pos + 4
+        }
+        if buf.len() > MAX_REQUEST_SIZE {
+            return Err(Response::error(413, "Request too large"))
+        }
+
+        let size = socket.read(&mut chunk).await.map_err(|err| Response::error(400, err))?;
+        if size == 0 {
+            return Err(Response::error(400, "Incomplete request"))
+        }
+        buf.extend_from_slice(&chunk[.. size]);
+    };
+
+    let header = str::from_utf8(&buf[.. header_size])
+        .map_err(|_| Response::error(400, "Invalid request header"))?;
+    let mut lines = header.split("\r\n");
+
+    let mut request_line = lines.next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default().to_owned();
+    let path = request_line.next().unwrap_or_default().to_owned();
+
+    let mut content_length = 0;
+    for line in lines {
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse()
+                    .map_err(|_| Response::error(400, "Invalid Content-Length"))?;
+            }
+        }
+    }
+
+    if content_length > MAX_REQUEST_SIZE {
+        return Err(Response::error(413, "Request too large"))
+    }
+
+    let mut body = buf.split_off(header_size);
+    while body.len() < content_length {
+        let size = socket.read(&mut chunk).await.map_err(|err| Response::error(400, err))?;
+        if size == 0 {
+            return Err(Response::error(400, "Incomplete request"))
+        }
+        body.extend_from_slice(&chunk[.. size]);
+    }
+    body.truncate(content_length);
+
+    Ok((method, path, body))
+}
+
+
+async fn handle(mut socket: TcpStream, tx: UnboundedSender<ApiRequest>) -> Result<()> {
+    let request = timeout(REQUEST_TIMEOUT, read_request(&mut socket)).await
+        .unwrap_or_else(|_| Err(Response::error(408, "Request timeout")));
+
+    let response = match request {
+        Ok((method, path, body)) => match route(&method, &path, &body) {
+            Ok(command) => {
+                let (reply, rx) = oneshot::channel();
+                tx.send((command, reply)).ok();
+                rx.await.unwrap_or_else(|_| Response::error(500, "Application is not available"))
+            }
+            Err(response) => response,
+        },
+        Err(response) => response,
+    };
+
+    let header = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len(),
+    );
+
+    socket.write_all(header.as_bytes()).await?;
+    socket.write_all(&response.body).await?;
+    socket.shutdown().await?;
+
+    Ok(())
+}
+
+
+/// HTTP API server. Requests are passed to the application with `tx`.
+pub async fn serve(api: &Api, tx: UnboundedSender<ApiRequest>) -> Result<()> {
+    let listener = TcpListener::bind((api.address.as_str(), api.port)).await
+        .with_context(|| format!("Failed to bind HTTP API on {}:{}", &api.address, api.port))?;
+
+    let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+
+    loop {
+        // new connections are not accepted until one of the active is closed
+        let permit = connections.clone().acquire_owned().await?;
+        let (socket, _) = listener.accept().await?;
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            if let Err(err) = handle(socket, tx).await {
+                debug!("HTTP API: {:#}", err);
+            }
+            drop(permit);
+        });
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{
+        route,
+        Command,
+    };
+
+
+    #[test]
+    fn routes() {
+        assert!(matches!(route("GET", "/streams", b""), Ok(Command::List)));
+        assert!(matches!(route("GET", "/streams/a", b""), Ok(Command::Get(name)) if name == "a"));
+        assert!(matches!(route("POST", "/streams/a/restart", b""), Ok(Command::Restart(name)) if name == "a"));
+        assert!(matches!(route("DELETE", "/streams/a", b""), Err(response) if response.status == 405));
+        assert!(matches!(route("GET", "/unknown", b""), Err(response) if response.status == 404));
+
+        let body = br#"{
+            "name": "b",
+            "input": { "type": "udp", "address": "239.0.0.1", "port": 1234 },
+            "output": { "type": "udp", "address": "127.0.0.1", "port": 10000 }
+        }"#;
+        assert!(matches!(route("POST", "/streams", body), Ok(Command::Add(stream)) if stream.name == "b"));
+        assert!(matches!(route("POST", "/streams", b"{}"), Err(response) if response.status == 400));
+    }
+}
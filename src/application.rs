@@ -1,7 +1,17 @@
 use {
     std::{
+        fmt,
+        io,
         pin::Pin,
+        sync::{
+            Arc,
+            atomic::Ordering,
+        },
         time::Duration,
+        collections::{
+            HashMap,
+            HashSet,
+        },
     },
 
     tokio::{
@@ -10,192 +20,1163 @@ use {
             AsyncReadExt,
             AsyncWriteExt,
         },
-        time::sleep,
+        time::{
+            sleep,
+            sleep_until,
+            interval,
+            Instant,
+        },
+        sync::mpsc::{
+            self,
+            UnboundedSender,
+            UnboundedReceiver,
+        },
+        task::JoinHandle,
         signal::unix::{
             signal,
             SignalKind
         },
     },
-    anyhow::Result,
+    serde_json::{
+        json,
+        Value,
+    },
+    schemars::schema::RootSchema,
+    log::{
+        debug,
+        info,
+        warn,
+        error,
+    },
 
     super::{
         ts::{
             TsPacket,
+            TsIter,
             TS_PACKET_SIZE,
+            PCR_CLOCK,
+            pcr_delta,
+        },
+        es::{
+            PesPacket,
+            PTS_NONE,
+            pts_delta,
         },
-        es::PesPacket,
         misc::offset_calc,
         config::{
+            self,
+            Mux,
             Type,
             Config,
+            Log,
+            ErrorAction,
+            OutputError,
             Stream,
+            Format,
             parse_config,
+            STALL_TIMEOUT,
         },
         streams::{
             File,
             UdpStream,
+            MuxStream,
+            MuxMessage,
             AsyncStream,
         },
+        mux::{
+            Multiplexer,
+            Intervals,
+        },
+        monitor::Monitor,
+        pipeline::{
+            PidMap,
+            Pipeline,
+            ContinuityCheck,
+            CcRepair,
+            ProgramSelect,
+            NullStrip,
+            Stuffing,
+        },
+        stats::Stats,
+        error::Error,
+        metrics,
+        notify::Notifier,
+        api::{
+            serve,
+            Command,
+            Response,
+            ApiRequest,
+        },
     },
 };
 
 
-/// 90clocks = 1ms
-pub const PTS_CLOCK_MS: u64 = 90;
-pub const PTS_NONE: u64 = 1 << 33;
-pub const PTS_MAX: u64 = PTS_NONE - 1;
+/// Maximum gap between timestamps in 27MHz clocks. Larger gap is handled as discontinuity
+const MAX_TIME_DELTA: u64 = PCR_CLOCK;
+/// Maximum output delay. Pacing restarts if output is late more than this
+const MAX_LAG: Duration = Duration::from_secs(1);
 
-/// Converts PTS to milliseconds
-#[inline]
-pub fn pts_to_ms(pts: u64) -> u64 { pts / PTS_CLOCK_MS }
 
+/// Function to apply logging options from the configuration
+type LogHandler = dyn Fn(&Log) + Send + Sync;
 
-/// Returns difference between previous PTS and current PTS
-#[inline]
-pub fn pts_delta(last_pts: u64, current_pts: u64) -> u64 {
-    if current_pts >= last_pts {
-        current_pts - last_pts
-    } else {
-        current_pts + PTS_MAX - last_pts
-    }
+
+/// Output pacing by the stream PCR, or by PTS if stream has no PCR.
+struct Pacer {
+    start: Instant,
+    /// Stream time since start in 27MHz clocks
+    elapsed: u64,
+    last: Option<u64>,
+
+    pcr_pid: Option<u16>,
+    pts_last: u64,
 }
 
+impl Pacer {
+    fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            elapsed: 0,
+            last: None,
 
-async fn syssig(kind: SignalKind) -> Result<()> {
-    let mut stream = signal(kind)?;
-    stream.recv().await;
+            pcr_pid: None,
+            pts_last: PTS_NONE,
+        }
+    }
 
-    Ok(())
+    /// Returns wall clock time for the stream time in 27MHz clocks.
+    fn advance(&mut self, time: u64) -> Instant {
+        let now = Instant::now();
+
+        match self.last {
+            Some(last) if pcr_delta(last, time) <= MAX_TIME_DELTA => {
+                self.elapsed += pcr_delta(last, time);
+            }
+            _ => {
+                self.start = now;
+                self.elapsed = 0;
+            }
+        }
+        self.last = Some(time);
+
+        let deadline = self.start + Duration::from_nanos(self.elapsed * 1000 / 27);
+        if deadline + MAX_LAG < now {
+            self.start = now;
+            self.elapsed = 0;
+            return now
+        }
+
+        deadline
+    }
+
+    /// Returns time to send packets if `ts` has timestamp for pacing.
+    fn check(&mut self, ts: &TsPacket) -> Option<Instant> {
+        let pid = ts.get_pid();
+
+        if let Some(pcr) = ts.get_pcr() {
+            if self.pcr_pid.is_none() {
+                // switch from PTS to PCR
+                self.pcr_pid = Some(pid);
+                self.last = None;
+            }
+
+            if self.pcr_pid != Some(pid) {
+                return None
+            }
+
+            return Some(self.advance(pcr))
+        }
+
+        if self.pcr_pid.is_some() || ! (ts.is_pusi() && ts.is_payload()) || ! ts.is_pes() {
+            return None
+        }
+
+        let pts = PesPacket::new(ts.get_payload()).ok()?.get_pts()?;
+
+        // skip PTS of the reordered frames
+        if self.pts_last != PTS_NONE {
+            let forward = pts_delta(self.pts_last, pts);
+            let backward = pts_delta(pts, self.pts_last);
+            if forward == 0 || (backward < forward && backward * 300 <= MAX_TIME_DELTA) {
+                return None
+            }
+        }
+        self.pts_last = pts;
+
+        Some(self.advance(pts * 300))
+    }
 }
 
 
-async fn make_stream(stream_type: &Type) -> Result<Pin<Box<dyn AsyncStream>>> {
+/// Multiplexer inputs by multiplexer name
+type MuxMap = HashMap<String, UnboundedSender<MuxMessage>>;
+
+
+/// Multiplexer output timer interval
+const MUX_TICK: Duration = Duration::from_millis(10);
+/// Delay before the next read at the end of input
+const EOF_DELAY: Duration = Duration::from_millis(50);
+/// Delay before reopening input after error
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+/// Statistics update interval
+const STATS_TICK: Duration = Duration::from_secs(1);
+/// Statistics are logged every `REPORT_INTERVAL` ticks
+const REPORT_INTERVAL: u64 = 60;
+/// Stream task without progress for `STALL_TICKS` ticks is wedged
+const STALL_TICKS: u64 = STALL_TIMEOUT / STATS_TICK.as_millis() as u64;
+
+
+async fn make_stream(stream_type: &Type, mux: &MuxMap) -> Result<Pin<Box<dyn AsyncStream>>, Error> {
+    let open_error = |source| Error::Open { endpoint: stream_type.to_string(), source };
+
     match stream_type {
         Type::File { path } => {
-            Ok(Box::pin(File::open(&path).await?))
+            Ok(Box::pin(File::open(&path).await.map_err(open_error)?))
         },
         Type::Udp { address, port } => {
-            Ok(Box::pin(UdpStream::new((address.as_str(), *port)).await?))
+            Ok(Box::pin(UdpStream::new((address.as_str(), *port)).await.map_err(open_error)?))
+        },
+        Type::Mux { name } => {
+            match mux.get(name) {
+                Some(tx) => Ok(Box::pin(MuxStream::new(tx.clone()))),
+                None => Err(Error::MuxNotFound(name.clone())),
+            }
         },
     }
 }
 
 
-async fn play(stream: &Stream) -> Result<()> {
-    let mut input = make_stream(&stream.input).await?;
-    let mut output = make_stream(&stream.output).await?;
+fn make_pipeline(stream: &Stream, stats: &Arc<Stats>) -> Pipeline {
+    let mut pipeline = Pipeline::default();
 
-    let mut buf = [0; 1024 * TS_PACKET_SIZE];
+    if let Some(program) = &stream.program {
+        pipeline.push(ProgramSelect::new(program.pnr, program.name.clone(), program.si));
+    }
 
-    loop {
-        let mut pts_first = 0;
-        let mut pts_last = 0;
+    let pid_map = PidMap::new(
+        stream.filter.iter().copied(),
+        stream.remap.iter().map(|remap| (remap.from, remap.to)),
+    );
+    if ! pid_map.is_empty() {
+        pipeline.push(pid_map);
+    }
+
+    if stream.strip_null {
+        pipeline.push(NullStrip::new(stats.clone()));
+    }
+
+    if let Some(bitrate) = stream.bitrate {
+        pipeline.push(Stuffing::new(&stream.name, bitrate));
+    }
+
+    // the last stage, after all stages which drop, merge or insert packets
+    if stream.repair_cc {
+        pipeline.push(CcRepair::default());
+    }
+
+    pipeline
+}
+
+
+/// Writes data to the output. `endpoint` is used for the error context.
+async fn write_out(output: &mut Pin<Box<dyn AsyncStream>>, endpoint: &Type, data: &[u8]) -> Result<(), Error> {
+    let write_error = |source| Error::Write { endpoint: endpoint.to_string(), source };
+
+    let mut w_offset = 0;
+    while w_offset < data.len() {
+        let (start, end) = offset_calc(w_offset, data.len());
+        let offset = output.write(&data[start .. end]).await.map_err(write_error)?;
+        if offset == 0 {
+            return Err(write_error(io::ErrorKind::WriteZero.into()))
+        }
+        w_offset += offset;
+    }
 
-        let mut r_offset = 0;
-        let mut w_offset = 0;
+    Ok(())
+}
 
-        let mut pts_vec = Vec::new();
+
+/// Owner of the output.
+#[derive(Clone, Copy)]
+enum Owner<'a> {
+    Stream(&'a Stream, &'a Stats),
+    Mux(&'a Mux),
+}
+
+impl<'a> Owner<'a> {
+    #[inline]
+    fn endpoint(self) -> &'a Type {
+        match self {
+            Owner::Stream(stream, _) => &stream.output,
+            Owner::Mux(mux) => &mux.output,
+        }
+    }
+
+    #[inline]
+    fn policy(self) -> &'a OutputError {
+        match self {
+            Owner::Stream(stream, _) => &stream.output_error,
+            Owner::Mux(mux) => &mux.output_error,
+        }
+    }
+
+    fn warn(self, message: fmt::Arguments) {
+        match self {
+            Owner::Stream(stream, _) => warn!(stream = stream.name.as_str(); "{}", message),
+            Owner::Mux(mux) => warn!(mux = mux.name.as_str(); "{}", message),
+        }
+    }
+}
+
+
+/// Stream or multiplexer output with the error policy.
+struct Output<'a> {
+    owner: Owner<'a>,
+    mux: &'a MuxMap,
+    inner: Pin<Box<dyn AsyncStream>>,
+    /// Delay after the last error
+    delay: Duration,
+    /// Time of the last error
+    last_error: Option<Instant>,
+}
+
+impl<'a> Output<'a> {
+    async fn open(owner: Owner<'a>, mux: &'a MuxMap) -> Result<Output<'a>, Error> {
+        Ok(Self {
+            owner,
+            mux,
+            inner: make_stream(owner.endpoint(), mux).await?,
+            delay: Duration::ZERO,
+            last_error: None,
+        })
+    }
+
+    /// Writes data to the output. On error data is dropped and the error is handled
+    /// according to the policy. Returns `false` if data is dropped,
+    /// or error only if the stream should be stopped.
+    async fn write(&mut self, data: &[u8]) -> Result<bool, Error> {
+        let err = match write_out(&mut self.inner, self.owner.endpoint(), data).await {
+            Ok(()) => return Ok(true),
+            Err(err) => err,
+        };
+
+        let stats = match self.owner {
+            Owner::Stream(_, stats) => Some(stats),
+            Owner::Mux(_) => None,
+        };
+        if let Some(stats) = stats {
+            stats.output_errors.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let policy = self.owner.policy();
+        if policy.action == ErrorAction::Stop {
+            return Err(err)
+        }
+
+        // connected UDP socket fails on every second write while the receiver is not available,
+        // so delay is reset only if output works longer than the maximum delay
+        let max_delay = Duration::from_millis(policy.max_delay);
+        let now = Instant::now();
+        self.delay = match self.last_error {
+            Some(last) if now - last <= self.delay + max_delay => (self.delay * 2).min(max_delay),
+            _ => Duration::from_millis(policy.delay),
+        };
+        self.last_error = Some(now);
+
+        self.owner.warn(format_args!("{}, retry in {}ms", err, self.delay.as_millis()));
+
+        // task in the backoff is not wedged
+        if let Some(stats) = stats {
+            stats.waiting.store(true, Ordering::Relaxed);
+        }
+        sleep(self.delay).await;
+        if let Some(stats) = stats {
+            stats.waiting.store(false, Ordering::Relaxed);
+        }
+
+        if policy.action == ErrorAction::Reopen {
+            match make_stream(self.owner.endpoint(), self.mux).await {
+                Ok(inner) => self.inner = inner,
+                Err(err) => self.owner.warn(format_args!("{}", err)),
+            }
+        }
+
+        Ok(false)
+    }
+}
+
+
+async fn play(stream: &Stream, mux: &MuxMap, stats: &Arc<Stats>) -> Result<(), Error> {
+    if let Type::Mux { name } = &stream.input {
+        return Err(Error::MuxUsage(name.clone()))
+    }
+
+    let mut input = make_stream(&stream.input, mux).await?;
+    let mut output = Output::open(Owner::Stream(stream, stats), mux).await?;
+    stats.opened.store(true, Ordering::Relaxed);
+
+    let mut pipeline = make_pipeline(stream, stats);
+    let mut cc = ContinuityCheck::new(stats.clone());
+
+    let mut pacer = Pacer::new();
+    let mut monitor = Monitor::new(stats.clone());
+
+    let mut buf = [0; 1024 * TS_PACKET_SIZE];
+    let mut out = Vec::with_capacity(buf.len());
+
+    // partial packet from the previous read
+    let mut tail = 0;
+    let mut synced = true;
+
+    loop {
+        let mut r_offset = tail;
 
         loop {
-            let offset = input.read(&mut buf[r_offset.. ]).await.unwrap();
+            stats.waiting.store(true, Ordering::Relaxed);
+            let result = input.read(&mut buf[r_offset .. ]).await;
+            stats.waiting.store(false, Ordering::Relaxed);
+
+            let offset = match result {
+                Ok(v) => v,
+                Err(source) => {
+                    let err = Error::Read { endpoint: stream.input.to_string(), source };
+                    warn!(stream = stream.name.as_str(); "{}", err);
+                    sleep(RECONNECT_DELAY).await;
+                    input = make_stream(&stream.input, mux).await?;
+                    stats.input_reconnects.fetch_add(1, Ordering::Relaxed);
+                    // data of the previous connection is dropped
+                    r_offset = 0;
+                    tail = 0;
+                    synced = true;
+                    continue
+                }
+            };
             r_offset += offset;
             if offset == 0 {
                 break
             }
         }
 
-        let mut cnt = 0;
-        while cnt < r_offset {
-            match TsPacket::new(&buf[cnt ..r_offset]) {
-                Ok(ts) => {
-                    cnt += TS_PACKET_SIZE;
-                    if ! (ts.is_pusi() && ts.is_payload()) {
-                        continue
-                    }
+        if r_offset == tail && stream.repeat {
+            // end of input. partial packet is dropped
+            tail = 0;
+            synced = true;
+            input = make_stream(&stream.input, mux).await?;
+            stats.loops.fetch_add(1, Ordering::Relaxed);
+            debug!(stream = stream.name.as_str(), input:% = stream.input; "restart from the beginning");
+            continue
+        }
 
-                    if ! ts.is_pes() {
-                        continue
-                    }
+        if r_offset == tail {
+            // end of input. wait for the appended data
+            stats.waiting.store(true, Ordering::Relaxed);
+            sleep(EOF_DELAY).await;
+            stats.waiting.store(false, Ordering::Relaxed);
+            continue
+        }
 
-                    let pes = PesPacket::from(ts);
-                    if ! (pes.is_syntax_spec() && pes.is_pts()) {
-                        continue
-                    }
+        stats.input_bytes.fetch_add((r_offset - tail) as u64, Ordering::Relaxed);
 
-                    if let Some(pts) = pes.get_pts() {
-                        pts_vec.push(pts);
+        let mut packets = TsIter::resume(&buf[.. r_offset], synced);
+        for ts in &mut packets {
+            stats.input_packets.fetch_add(1, Ordering::Relaxed);
+            cc.push(&ts);
+            pipeline.process(&ts, &mut out);
 
-                        if pts_first == 0 {
-                            pts_first = pts;
-                            pts_last = pts;
-                        }
+            if let Some(deadline) = pacer.check(&ts) {
+                sleep_until(deadline).await;
+                monitor.push(&out, deadline);
+                if output.write(&out).await? {
+                    stats.push(&out);
+                }
+                out.clear();
+            } else if out.len() >= buf.len() {
+                // no timestamps to pace the output, flush without delay
+                monitor.push(&out, Instant::now());
+                if output.write(&out).await? {
+                    stats.push(&out);
+                }
+                out.clear();
+            }
+        }
 
-                        if pts > pts_last {
-                            let delta = pts_to_ms(
-                                pts_delta(pts_first, pts)
-                            );
+        synced = packets.is_synced();
+        tail = packets.remainder().len();
+        buf.copy_within(r_offset - tail .. r_offset, 0);
+    }
+}
 
-                            pts_first = pts;
-                            pts_last = pts;
 
-                            loop {
-                                let (start, end) = offset_calc(w_offset, cnt);
-                                let offset = output.write(&mut buf[start .. end]).await.unwrap();
-                                w_offset += offset;
-                                if offset == 0 {
-                                    break
-                                }
-                            }
+async fn remux(mux: &Mux, mut rx: UnboundedReceiver<MuxMessage>) -> Result<(), Error> {
+    if let Type::Mux { name } = &mux.output {
+        return Err(Error::MuxUsage(name.clone()))
+    }
 
-                            sleep(Duration::from_millis(50)).await;
-                        }
-                    };
+    let no_mux = MuxMap::new();
+    let mut output = Output::open(Owner::Mux(mux), &no_mux).await?;
+
+    let mut multiplexer = Multiplexer::new(mux.tsid, mux.onid, mux.bitrate);
+    multiplexer.set_intervals(Intervals {
+        pat: Duration::from_millis(mux.psi_interval.pat),
+        pmt: Duration::from_millis(mux.psi_interval.pmt),
+        sdt: Duration::from_millis(mux.psi_interval.sdt),
+        nit: Duration::from_millis(mux.psi_interval.nit),
+    });
+    if let Some(network_id) = mux.network_id {
+        multiplexer.set_network(network_id, &mux.network_name);
+    }
+    let mut out = Vec::new();
+
+    let mut timer = interval(MUX_TICK);
+    let start = Instant::now();
+    let mut sent = 0;
+
+    // number of packets to be sent since the start.
+    // u128 keeps the product of the elapsed time and bitrate from overflowing
+    let packets = || (start.elapsed().as_micros() * u128::from(mux.bitrate)
+        / (TS_PACKET_SIZE as u128 * 8 * 1_000_000)) as u64;
+
+    loop {
+        select! {
+            message = rx.recv() => match message {
+                Some(MuxMessage::Data(id, data)) => multiplexer.push(id, &data),
+                Some(MuxMessage::Close(id)) => multiplexer.close(id),
+                None => return Ok(()),
+            },
+            _ = timer.tick() => {
+                let total = packets();
+                let dropped = multiplexer.pull((total - sent) as usize, &mut out);
+                sent = total;
+
+                if dropped != 0 {
+                    warn!(mux = mux.name.as_str(); "inputs exceed output bitrate, {} packets dropped", dropped);
                 }
-                Err(_) => cnt += 1
+
+                if ! output.write(&out).await? {
+                    // skip the time of the backoff to avoid the burst after it
+                    sent = packets();
+                }
+                out.clear();
+            },
+        }
+    }
+}
+
+
+/// Stream definition with the running task.
+struct StreamTask {
+    config: Stream,
+    stats: Arc<Stats>,
+    task: Option<JoinHandle<()>>,
+
+    /// Progress of the task on the last check
+    progress: u64,
+    /// Number of the checks without progress
+    stalled: u64,
+}
+
+impl StreamTask {
+    fn new(config: Stream) -> Self {
+        Self {
+            config,
+            stats: Arc::new(Stats::default()),
+            task: None,
+
+            progress: 0,
+            stalled: 0,
+        }
+    }
+
+    fn start(&mut self, mux: &Arc<MuxMap>) {
+        self.stop();
+
+        self.stats = Arc::new(Stats::default());
+        self.progress = 0;
+        self.stalled = 0;
+
+        let stream = self.config.clone();
+        let stats = self.stats.clone();
+        let mux = mux.clone();
+        self.task = Some(tokio::spawn(async move {
+            info!(stream = stream.name.as_str(), input:% = stream.input, output:% = stream.output; "started");
+            match play(&stream, &mux, &stats).await {
+                Ok(()) => info!(stream = stream.name.as_str(); "finished"),
+                Err(err) => error!(
+                    stream = stream.name.as_str(), input:% = stream.input, output:% = stream.output;
+                    "{:#}", err
+                ),
             }
+        }));
+    }
+
+    fn stop(&mut self) {
+        if let Some(task) = self.task.take() {
+            task.abort();
+        }
+    }
+
+    fn is_running(&self) -> bool {
+        matches!(&self.task, Some(task) if ! task.is_finished())
+    }
+
+    /// Called on each statistics tick. Returns false if the task is wedged.
+    /// Task waiting for the input data is not wedged.
+    fn check(&mut self) -> bool {
+        let progress = self.stats.progress();
+        if ! self.is_running() || self.stats.waiting.load(Ordering::Relaxed) || progress != self.progress {
+            self.stalled = 0;
+        } else {
+            self.stalled += 1;
         }
+        self.progress = progress;
+
+        self.stalled < STALL_TICKS
+    }
+
+    fn state(&self) -> &'static str {
+        match &self.task {
+            None => "stopped",
+            Some(task) if task.is_finished() => "finished",
+            Some(_) => "running",
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "name": &self.config.name,
+            "state": self.state(),
+        })
     }
 }
 
 
+/// Playout engine. Runs streams and multiplexers from the configuration file,
+/// HTTP API, statistics and service manager notifications.
 pub struct Application {
-    pub config: Config,
+    /// Current configuration
+    config: Config,
     config_path: String,
+    config_format: Option<Format>,
+
+    mux: Arc<MuxMap>,
+    mux_tasks: Vec<JoinHandle<()>>,
+    streams: Vec<StreamTask>,
+    /// Streams added with the HTTP API. Kept on reload
+    added: Vec<Stream>,
+    /// Names of the streams stopped with the HTTP API. Not started on reload
+    stopped: HashSet<String>,
+
+    api_task: Option<JoinHandle<()>>,
+    api_tx: UnboundedSender<ApiRequest>,
+    api_rx: UnboundedReceiver<ApiRequest>,
+
+    notify: Notifier,
+    /// All stream tasks are progressing. Watchdog pings are sent while healthy
+    healthy: bool,
+    /// Last status sent to the service manager
+    status: String,
+    /// READY is sent to the service manager
+    ready: bool,
+
+    /// Applies logging options on reload
+    log_handler: Option<Box<LogHandler>>,
 }
 
 impl Application {
-    pub async fn new<S: ToString>(path: S) -> Result<Self> {
+    /// Loads configuration file. Format is defined by the file extension if `config_format` is not defined.
+    pub async fn new<S: ToString>(path: S, config_format: Option<Format>) -> Result<Self, Error> {
         let config_path = path.to_string();
+        let (api_tx, api_rx) = mpsc::unbounded_channel();
+
+        let config = parse_config(&config_path, config_format).await
+            .map_err(|err| Error::Config { path: config_path.clone(), source: err.into() })?;
+        debug!("{:?}", &config);
+
         let res = Self {
-            config: parse_config(&config_path).await?,
+            config,
             config_path,
+            config_format,
+
+            mux: Arc::new(MuxMap::new()),
+            mux_tasks: Vec::new(),
+            streams: Vec::new(),
+            added: Vec::new(),
+            stopped: HashSet::new(),
+
+            api_task: None,
+            api_tx,
+            api_rx,
+
+            notify: Notifier::from_env(),
+            healthy: true,
+            status: String::new(),
+            ready: false,
+
+            log_handler: None,
         };
 
         Ok(res)
     }
 
-    pub async fn run(&mut self) {
+    /// Returns JSON Schema of the configuration file.
+    pub fn schema() -> RootSchema {
+        config::schema()
+    }
+
+    /// Sets function to apply logging options from the configuration.
+    /// Function is called immediately and after each configuration reload.
+    /// Logger is not changed by the application itself.
+    pub fn on_log_config<F>(&mut self, f: F)
+    where
+        F: Fn(&Log) + Send + Sync + 'static,
+    {
+        f(&self.config.log);
+        self.log_handler = Some(Box::new(f));
+    }
+
+    /// Launches all multiplexers and streams from the configuration, and streams added with the HTTP API.
+    /// Streams stopped with the HTTP API are not started.
+    fn start(&mut self) {
+        let mut mux = MuxMap::new();
+
+        for item in &self.config.mux {
+            let (tx, rx) = mpsc::unbounded_channel();
+            mux.insert(item.name.clone(), tx);
+
+            let item = item.clone();
+            self.mux_tasks.push(tokio::spawn(async move {
+                if let Err(err) = remux(&item, rx).await {
+                    error!(mux = item.name.as_str(), output:% = item.output; "{:#}", err);
+                }
+            }));
+        }
+
+        self.mux = Arc::new(mux);
+
+        let config = &self.config;
+        self.added.retain(|stream| {
+            let defined = config.stream.iter().any(|item| item.name == stream.name);
+            if defined {
+                warn!(stream = stream.name.as_str(); "stream added with API is replaced by the configuration file");
+            }
+            ! defined
+        });
+
+        for stream in self.config.stream.iter().chain(self.added.iter()) {
+            let mut task = StreamTask::new(stream.clone());
+            if ! self.stopped.contains(&stream.name) {
+                task.start(&self.mux);
+            }
+            self.streams.push(task);
+        }
+
+        let streams = &self.streams;
+        self.stopped.retain(|name| streams.iter().any(|stream| &stream.config.name == name));
+    }
+
+    /// Stops all running multiplexers and streams.
+    fn stop(&mut self) {
+        for mut stream in self.streams.drain(..) {
+            stream.stop();
+        }
+
+        for task in self.mux_tasks.drain(..) {
+            task.abort();
+        }
+    }
+
+    /// Launches HTTP API server if it is defined in the configuration.
+    fn start_api(&mut self) {
+        if let Some(task) = self.api_task.take() {
+            task.abort();
+        }
+
+        if let Some(api) = self.config.api.clone() {
+            let tx = self.api_tx.clone();
+            self.api_task = Some(tokio::spawn(async move {
+                if let Err(err) = serve(&api, tx).await {
+                    error!("{:#}", err);
+                }
+            }));
+        }
+    }
+
+    /// Prints statistics of all streams.
+    fn report(&self) {
+        for stream in self.streams.iter().filter(|stream| stream.is_running()) {
+            info!(stream = stream.config.name.as_str(); "{}", stream.stats.summary());
+        }
+    }
+
+    /// Sends number of the running streams to the service manager if it is changed.
+    fn update_status(&mut self) {
+        let running = self.streams.iter().filter(|stream| stream.is_running()).count();
+        let status = format!("{} of {} streams running", running, self.streams.len());
+        if status != self.status {
+            self.notify.status(&status);
+            self.status = status;
+        }
+    }
+
+    /// Sends READY to the service manager when all started streams have opened input and output,
+    /// or finished with error. Multiplexers are not awaited.
+    fn check_ready(&mut self) {
+        if self.ready {
+            return
+        }
+
+        let opened = self.streams.iter()
+            .all(|stream| ! stream.is_running() || stream.stats.opened.load(Ordering::Relaxed));
+        if opened {
+            self.notify.ready();
+            self.ready = true;
+        }
+    }
+
+    /// Completes one second of the statistics. Statistics are logged periodically.
+    fn tick(&mut self, ticks: u64) {
+        let mut healthy = true;
+
+        for stream in &mut self.streams {
+            stream.stats.tick();
+            if ! stream.check() {
+                healthy = false;
+                if self.healthy {
+                    error!(stream = stream.config.name.as_str(); "stream task is not responding");
+                }
+            }
+        }
+
+        self.healthy = healthy;
+        self.update_status();
+        self.check_ready();
+
+        if ticks % REPORT_INTERVAL == 0 {
+            self.report();
+        }
+    }
+
+    fn find_stream(&mut self, name: &str) -> Result<&mut StreamTask, Response> {
+        self.streams.iter_mut()
+            .find(|stream| stream.config.name == name)
+            .ok_or_else(|| Response::error(404, format!("Stream \"{}\" not found", name)))
+    }
+
+    /// Handles request from the HTTP API.
+    async fn handle(&mut self, command: Command) -> Result<Response, Response> {
+        let mux = self.mux.clone();
+
+        let response = match command {
+            Command::List => {
+                let list: Vec<Value> = self.streams.iter()
+                    .map(|stream| json!({
+                        "name": &stream.config.name,
+                        "state": stream.state(),
+                        "stream": &stream.config,
+                    }))
+                    .collect();
+                Response::ok(json!(list))
+            }
+            Command::Stats => {
+                let stats: serde_json::Map<String, Value> = self.streams.iter()
+                    .map(|stream| (stream.config.name.clone(), stream.stats.to_json()))
+                    .collect();
+                Response::ok(Value::Object(stats))
+            }
+            Command::Metrics => {
+                let streams: Vec<(&str, bool, &Stats)> = self.streams.iter()
+                    .map(|stream| (stream.config.name.as_str(), stream.is_running(), stream.stats.as_ref()))
+                    .collect();
+                Response::text(metrics::render(&streams))
+            }
+            Command::Get(name) => {
+                let stream = self.find_stream(&name)?;
+                Response::ok(json!({
+                    "name": &stream.config.name,
+                    "state": stream.state(),
+                    "stream": &stream.config,
+                    "stats": stream.stats.to_json(),
+                }))
+            }
+            Command::Start(name) => {
+                let stream = self.find_stream(&name)?;
+                if stream.is_running() {
+                    return Err(Response::error(409, format!("Stream \"{}\" is already running", name)))
+                }
+                stream.start(&mux);
+                let response = Response::ok(stream.to_json());
+                self.stopped.remove(&name);
+                response
+            }
+            Command::Stop(name) => {
+                let stream = self.find_stream(&name)?;
+                stream.stop();
+                let response = Response::ok(stream.to_json());
+                self.stopped.insert(name);
+                response
+            }
+            Command::Restart(name) => {
+                let stream = self.find_stream(&name)?;
+                stream.start(&mux);
+                let response = Response::ok(stream.to_json());
+                self.stopped.remove(&name);
+                response
+            }
+            Command::Reload(name) => {
+                let config = parse_config(&self.config_path, self.config_format).await
+                    .map_err(|err| Response::error(
+                        500,
+                        format!("Failed to load configuration file \"{}\": {:#}", &self.config_path, err),
+                    ))?;
+                let definition = config.stream.into_iter()
+                    .find(|stream| stream.name == name)
+                    .ok_or_else(|| Response::error(404, format!("Stream \"{}\" not found in configuration", name)))?;
+
+                let stream = self.find_stream(&name)?;
+                stream.config = definition;
+                stream.start(&mux);
+                let response = Response::ok(stream.to_json());
+                self.stopped.remove(&name);
+                self.added.retain(|stream| stream.name != name);
+                response
+            }
+            Command::Add(definition) => {
+                self.config.validate_stream(&definition)
+                    .map_err(|err| Response::error(400, format!("Invalid stream definition: {}", err)))?;
+                if self.find_stream(&definition.name).is_ok() {
+                    return Err(Response::error(409, format!("Stream \"{}\" already exists", &definition.name)))
+                }
+
+                self.added.push((*definition).clone());
+                let mut stream = StreamTask::new(*definition);
+                stream.start(&mux);
+                let response = Response::json(201, &stream.to_json());
+                self.streams.push(stream);
+                response
+            }
+        };
+
+        Ok(response)
+    }
+
+    /// Starts all streams and runs until SIGTERM or SIGINT.
+    /// SIGHUP reloads configuration and restarts streams, SIGUSR1 logs statistics.
+    /// Streams added or stopped with the HTTP API keep their state on reload.
+    pub async fn run(&mut self) -> Result<(), Error> {
+        // signal streams are kept between iterations to not miss signals
+        let mut hangup = signal(SignalKind::hangup()).map_err(Error::Signal)?;
+        let mut terminate = signal(SignalKind::terminate()).map_err(Error::Signal)?;
+        let mut interrupt = signal(SignalKind::interrupt()).map_err(Error::Signal)?;
+        let mut user1 = signal(SignalKind::user_defined1()).map_err(Error::Signal)?;
+
+        let mut timer = interval(STATS_TICK);
+        let mut ticks = 0;
+
+        let watchdog_interval = self.notify.watchdog_interval();
+        let mut watchdog = interval(watchdog_interval.unwrap_or(STATS_TICK));
+
+        self.start_api();
+
         loop {
-            select! {
-                _ = syssig(SignalKind::hangup()) => {
-                    match parse_config(&self.config_path).await {
-                        Ok(config) => {
-                            dbg!(&config);
-                            self.config = config;
-                        },
-                        Err(err) => {
-                            dbg!(err);
+            self.start();
+            self.update_status();
+            self.ready = false;
+            self.check_ready();
+
+            let reload = loop {
+                select! {
+                    _ = hangup.recv() => break true,
+                    _ = terminate.recv() => break false,
+                    _ = interrupt.recv() => break false,
+                    _ = user1.recv() => self.report(),
+                    _ = timer.tick() => {
+                        ticks += 1;
+                        self.tick(ticks);
+                    }
+                    _ = watchdog.tick(), if watchdog_interval.is_some() => {
+                        if self.healthy {
+                            self.notify.watchdog();
                         }
                     }
+                    Some((command, reply)) = self.api_rx.recv() => {
+                        let response = self.handle(command).await.unwrap_or_else(|err| err);
+                        reply.send(response).ok();
+                    }
+                }
+            };
+
+            if reload {
+                self.notify.reloading();
+            } else {
+                self.notify.stopping();
+            }
+
+            self.stop();
+
+            if ! reload {
+                break
+            }
+
+            match parse_config(&self.config_path, self.config_format).await {
+                Ok(config) => {
+                    if let Some(f) = &self.log_handler {
+                        f(&config.log);
+                    }
+                    info!("configuration reloaded");
+                    debug!("{:?}", &config);
+                    let api_changed = config.api != self.config.api;
+                    self.config = config;
+                    if api_changed {
+                        self.start_api();
+                    }
                 },
-                _ = syssig(SignalKind::terminate()) => {
-                    break;
-                },
-                _ = syssig(SignalKind::interrupt()) => {
-                    break;
-                },
-                _ = play(&self.config.stream[0]) => {},
+                Err(err) => {
+                    error!("failed to reload configuration file \"{}\": {:#}", &self.config_path, err);
+                }
             }
         }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        std::{
+            sync::Arc,
+            time::Duration,
+            error::Error as _,
+        },
+
+        tokio::time::Instant,
+        serde_json::json,
+
+        crate::{
+            error::Error,
+            ts::{
+                TsPacket,
+                TsIter,
+                TS_PACKET_SIZE,
+            },
+            es::{
+                Packetizer,
+                PesHeader,
+            },
+            stats::Stats,
+        },
+
+        super::{
+            Application,
+            Pacer,
+            make_pipeline,
+        },
+    };
+
+
+    fn pcr(pid: u16, pcr: u64) -> Vec<u8> {
+        let mut out = Vec::new();
+        Packetizer::new(pid).push_pcr(pcr, &mut out);
+        out
+    }
+
+    fn pts(pts: u64) -> Vec<u8> {
+        let pes = PesHeader { stream_id: 0xE0, pts: Some(pts), dts: None }.build(&[0x00; 16]);
+        let mut out = Vec::new();
+        Packetizer::new(0x100).push(&pes, None, false, &mut out);
+        out
+    }
+
+    fn check(pacer: &mut Pacer, data: &[u8]) -> Option<Instant> {
+        pacer.check(&TsPacket::new(data).unwrap())
+    }
+
+    #[test]
+    fn pcr_pacing() {
+        let mut pacer = Pacer::new();
+
+        let first = check(&mut pacer, &pcr(0x100, 27_000_000)).unwrap();
+        let second = check(&mut pacer, &pcr(0x100, 27_000_000 + 2_700_000)).unwrap();
+        assert_eq!(second - first, Duration::from_millis(100));
+
+        // PCR on other PID is ignored
+        assert_eq!(check(&mut pacer, &pcr(0x200, 0)), None);
+
+        // discontinuity restarts pacing from the current time
+        let before = Instant::now();
+        let third = check(&mut pacer, &pcr(0x100, 1_000 * 27_000_000)).unwrap();
+        assert!(third >= before && third < second);
+        let fourth = check(&mut pacer, &pcr(0x100, 1_000 * 27_000_000 + 2_700_000)).unwrap();
+        assert_eq!(fourth - third, Duration::from_millis(100));
+
+        // output late more than MAX_LAG
+        pacer.start -= Duration::from_secs(5);
+        let before = Instant::now();
+        let late = check(&mut pacer, &pcr(0x100, 1_000 * 27_000_000 + 5_400_000)).unwrap();
+        assert!(late >= before);
+        let next = check(&mut pacer, &pcr(0x100, 1_000 * 27_000_000 + 8_100_000)).unwrap();
+        assert_eq!(next - late, Duration::from_millis(100));
+    }
+
+    #[test]
+    fn pts_pacing() {
+        let mut pacer = Pacer::new();
+
+        let first = check(&mut pacer, &pts(90_000)).unwrap();
+        let second = check(&mut pacer, &pts(90_000 + 3_600 * 2)).unwrap();
+        assert_eq!(second - first, Duration::from_millis(80));
+
+        // reordered frame and repeated PTS are skipped
+        assert_eq!(check(&mut pacer, &pts(90_000 + 3_600)), None);
+        assert_eq!(check(&mut pacer, &pts(90_000 + 3_600 * 2)), None);
+
+        let third = check(&mut pacer, &pts(90_000 + 3_600 * 4)).unwrap();
+        assert_eq!(third - first, Duration::from_millis(160));
+
+        // PCR has priority over PTS
+        assert!(check(&mut pacer, &pcr(0x101, 0)).is_some());
+        assert_eq!(check(&mut pacer, &pts(90_000 + 3_600 * 6)), None);
+    }
+
+
+    /// Returns TS packet with payload.
+    fn packet(pid: u16, cc: u8) -> Vec<u8> {
+        let mut ts = vec![0x47, (pid >> 8) as u8, pid as u8, 0x10 | cc];
+        ts.resize(TS_PACKET_SIZE, 0xFF);
+        ts
+    }
+
+    #[test]
+    fn pipeline_cc() {
+        let stream = serde_json::from_value(json!({
+            "name": "a",
+            "input": { "type": "file", "path": "a.ts" },
+            "output": { "type": "file", "path": "b.ts" },
+            "filter": [ 0x200 ],
+            "remap": [ { "from": 0x100, "to": 0x300 } ],
+            "repair_cc": true,
+        })).unwrap();
+        let mut pipeline = make_pipeline(&stream, &Arc::new(Stats::default()));
+
+        // PID 0x100 is merged with 0x300, PID 0x200 is dropped
+        let mut out = Vec::new();
+        for i in 0 .. 8 {
+            for ts in [packet(0x100, i), packet(0x200, i), packet(0x300, i + 8)].iter() {
+                pipeline.process(&TsPacket::new(ts).unwrap(), &mut out);
+            }
+        }
+
+        let cc: Vec<u8> = TsIter::new(&out).map(|ts| ts.get_cc()).collect();
+        assert!(TsIter::new(&out).all(|ts| ts.get_pid() == 0x300));
+        assert_eq!(cc.len(), 16);
+        assert!(cc.windows(2).all(|w| w[1] == (w[0] + 1) & 0x0F));
+    }
+
+    #[tokio::test]
+    async fn config_error() {
+        let err = Application::new("/nonexistent/tsplay.json", None).await.err().unwrap();
+        assert!(matches!(&err, Error::Config { path, .. } if path == "/nonexistent/tsplay.json"));
+        assert!(err.source().unwrap().to_string().contains("/nonexistent/tsplay.json"));
     }
 }
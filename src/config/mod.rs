@@ -0,0 +1,445 @@
+mod validate;
+mod resolve;
+
+
+use {
+    std::{
+        fmt,
+        path::{
+            Path,
+            PathBuf,
+        },
+        str::FromStr,
+    },
+
+    serde::{
+        self,
+        Serialize,
+        Deserialize,
+    },
+    serde_json::Value,
+    schemars::{
+        schema_for,
+        schema::RootSchema,
+        JsonSchema,
+    },
+    anyhow::{
+        anyhow,
+        Result,
+        Context,
+    },
+};
+
+
+/// Stream task without progress for this time in milliseconds is wedged.
+/// Output error delay should be shorter to keep the task alive.
+pub const STALL_TIMEOUT: u64 = 10_000;
+
+
+/// Root of the configuration file.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Config {
+    #[schemars(length(min = 1))]
+    pub stream: Vec<Stream>,
+    #[serde(default)]
+    pub mux: Vec<Mux>,
+    /// HTTP control and status API
+    pub api: Option<Api>,
+    #[serde(default)]
+    pub log: Log,
+}
+
+
+/// Logging options. Command line options take precedence.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Log {
+    /// Destination and format of the messages
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Enable debug messages
+    #[serde(default)]
+    pub debug: bool,
+}
+
+
+/// Destination and format of the log messages.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable lines to the stderr
+    #[default]
+    Text,
+    /// JSON object per line to the stderr
+    Json,
+    /// Local syslog daemon on the /dev/log
+    Syslog,
+    /// Native protocol of the systemd-journald
+    Journald,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            "syslog" => Ok(LogFormat::Syslog),
+            "journald" => Ok(LogFormat::Journald),
+            _ => Err(format!("unknown log format \"{}\"", s)),
+        }
+    }
+}
+
+
+/// Address of the HTTP API server.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Api {
+    pub address: String,
+    #[schemars(range(min = 1))]
+    pub port: u16,
+}
+
+
+/// Stream from the input to the output with processing options.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Stream {
+    pub name: String,
+    pub input: Type,
+    pub output: Type,
+    /// Single program to extract from the MPTS
+    pub program: Option<Program>,
+    /// PIDs to drop from the output
+    #[serde(default)]
+    #[schemars(inner(range(max = 8191)))]
+    pub filter: Vec<u16>,
+    /// PIDs to renumber on the output
+    #[serde(default)]
+    pub remap: Vec<Remap>,
+    /// Remove null packets from the output
+    #[serde(default)]
+    pub strip_null: bool,
+    /// Renumber continuity counters on the output
+    #[serde(default)]
+    pub repair_cc: bool,
+    /// Constant output bitrate in bits per second. Stream is padded with null packets
+    pub bitrate: Option<u64>,
+    /// Restart input from the beginning at the end of file
+    #[serde(default, rename = "loop")]
+    pub repeat: bool,
+    /// Handling of the output errors
+    #[serde(default)]
+    pub output_error: OutputError,
+}
+
+
+/// Action on the output error.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorAction {
+    /// Drop data and continue writing to the same output after delay
+    #[default]
+    Retry,
+    /// Drop data and open the output again after delay
+    Reopen,
+    /// Stop the stream
+    Stop,
+}
+
+
+#[inline]
+fn default_error_delay() -> u64 { 100 }
+
+#[inline]
+fn default_error_max_delay() -> u64 { 5_000 }
+
+
+/// Output error policy. Delay is doubled on each consecutive error up to `max_delay`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct OutputError {
+    #[serde(default)]
+    pub action: ErrorAction,
+    /// Delay after the first error in milliseconds
+    #[serde(default = "default_error_delay")]
+    pub delay: u64,
+    /// Maximum delay in milliseconds, should be less than `STALL_TIMEOUT`
+    #[serde(default = "default_error_max_delay")]
+    pub max_delay: u64,
+}
+
+impl Default for OutputError {
+    fn default() -> Self {
+        Self {
+            action: ErrorAction::default(),
+            delay: default_error_delay(),
+            max_delay: default_error_max_delay(),
+        }
+    }
+}
+
+
+/// Program selection from the MPTS.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Program {
+    /// program_number of the program
+    pub pnr: Option<u16>,
+    /// service name from the SDT, used if `pnr` is not defined
+    pub name: Option<String>,
+    /// keep NIT, TDT/TOT and SDT/EIT of the program
+    #[serde(default)]
+    pub si: bool,
+}
+
+
+/// PID renumbering.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Remap {
+    #[schemars(range(max = 8191))]
+    pub from: u16,
+    #[schemars(range(max = 8191))]
+    pub to: u16,
+}
+
+
+/// Input or output of the stream.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Type {
+    /// Local file
+    File { path: String },
+    /// UDP unicast or multicast address. IPv4 only
+    Udp {
+        address: String,
+        #[schemars(range(min = 1))]
+        port: u16,
+    },
+    /// Multiplexer with given name. Could be used as stream output only
+    Mux { name: String },
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::File { path } => write!(f, "file://{}", path),
+            Type::Udp { address, port } => write!(f, "udp://{}:{}", address, port),
+            Type::Mux { name } => write!(f, "mux://{}", name),
+        }
+    }
+}
+
+
+#[inline]
+fn default_id() -> u16 { 1 }
+
+
+/// Multi-program transport stream multiplexer.
+/// Streams are added to the multiplexer with output `{ "type": "mux", "name": "..." }`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct Mux {
+    pub name: String,
+    pub output: Type,
+    /// Output bitrate in bits per second
+    pub bitrate: u64,
+    /// transport_stream_id of the output
+    #[serde(default = "default_id")]
+    pub tsid: u16,
+    /// original_network_id of the output
+    #[serde(default = "default_id")]
+    pub onid: u16,
+    /// network_id of the NIT. NIT is not sent if not defined
+    pub network_id: Option<u16>,
+    /// network_name of the NIT
+    #[serde(default)]
+    pub network_name: String,
+    /// Repetition intervals of the PSI/SI tables
+    #[serde(default)]
+    pub psi_interval: PsiInterval,
+    /// Handling of the output errors
+    #[serde(default)]
+    pub output_error: OutputError,
+}
+
+
+/// Repetition intervals of the PSI/SI tables in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct PsiInterval {
+    pub pat: u64,
+    pub pmt: u64,
+    pub sdt: u64,
+    pub nit: u64,
+}
+
+impl Default for PsiInterval {
+    fn default() -> Self {
+        Self {
+            pat: 100,
+            pmt: 100,
+            sdt: 1000,
+            nit: 10_000,
+        }
+    }
+}
+
+
+/// Format of the configuration file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// JSON, used for unknown extensions
+    Json,
+    /// TOML, `.toml` extension
+    Toml,
+    /// YAML, `.yaml` or `.yml` extension
+    Yaml,
+}
+
+impl Format {
+    /// Returns format by the file extension. JSON is used for unknown extensions.
+    pub fn from_path(path: &str) -> Self {
+        match Path::new(path).extension().and_then(|v| v.to_str()) {
+            Some("toml") => Format::Toml,
+            Some("yaml") | Some("yml") => Format::Yaml,
+            _ => Format::Json,
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Format::Json),
+            "toml" => Ok(Format::Toml),
+            "yaml" => Ok(Format::Yaml),
+            _ => Err(format!("unknown configuration format \"{}\"", s)),
+        }
+    }
+}
+
+
+/// Returns JSON Schema of the configuration file.
+/// Includes and templates are not described, the schema is for the resolved configuration.
+pub fn schema() -> RootSchema {
+    schema_for!(Config)
+}
+
+
+/// Builds configuration from the document with resolved includes.
+fn build(mut value: Value) -> Result<Config> {
+    resolve::apply_templates(&mut value)?;
+    resolve::coerce(&mut value, &serde_json::to_value(schema())?);
+    let config = serde_path_to_error::deserialize(value)
+        .map_err(|err| anyhow!("{}: {}", err.path(), err.inner()))?;
+    Ok(config)
+}
+
+
+/// Reads and validates configuration file with all included files.
+/// Format is defined by the file extension if `format` is not defined.
+pub async fn parse_config(path: &str, format: Option<Format>) -> Result<Config> {
+    let format = format.unwrap_or_else(|| Format::from_path(path));
+    let value = resolve::load(PathBuf::from(path), format, 0).await?;
+
+    let config = build(value)
+        .context("Failed to parse configuration")?;
+
+    config.validate()
+        .context("Invalid configuration")?;
+
+    Ok(config)
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{
+        build,
+        schema,
+        resolve::parse_value,
+        Config,
+        Format,
+    };
+
+
+    fn parse(data: &[u8], format: Format) -> Config {
+        build(parse_value(data, format).unwrap()).unwrap()
+    }
+
+
+    #[test]
+    fn formats() {
+        let json = br#"{
+            "stream": [
+                {
+                    "name": "a",
+                    "input": { "type": "udp", "address": "239.0.0.1", "port": 1234 },
+                    "output": { "type": "file", "path": "a.ts" },
+                    "remap": [ { "from": 256, "to": 512 } ],
+                    "repair_cc": true
+                }
+            ],
+            "api": { "address": "127.0.0.1", "port": 8080 }
+        }"#;
+
+        let toml = br#"
+            # comment
+            [api]
+            address = "127.0.0.1"
+            port = 8080
+
+            [[stream]]
+            name = "a"
+            input = { type = "udp", address = "239.0.0.1", port = 1234 }
+            output = { type = "file", path = "a.ts" }
+            remap = [ { from = 256, to = 512 } ]
+            repair_cc = true
+        "#;
+
+        let yaml = br#"
+            # comment
+            stream:
+              - name: a
+                input: { type: udp, address: 239.0.0.1, port: 1234 }
+                output:
+                  type: file
+                  path: a.ts
+                remap:
+                  - { from: 256, to: 512 }
+                repair_cc: true
+            api:
+              address: 127.0.0.1
+              port: 8080
+        "#;
+
+        let expected = serde_json::to_value(parse(json, Format::Json)).unwrap();
+        assert_eq!(serde_json::to_value(parse(toml, Format::Toml)).unwrap(), expected);
+        assert_eq!(serde_json::to_value(parse(yaml, Format::Yaml)).unwrap(), expected);
+
+        let err = parse_value(b"[[stream]]\nname = \n", Format::Toml).unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+
+        let value = parse_value(br#"{ "stream": [ { "name": 1 } ] }"#, Format::Json).unwrap();
+        assert!(build(value).unwrap_err().to_string().starts_with("stream[0].name: invalid type"));
+
+        assert_eq!(Format::from_path("/etc/tsplay.yml"), Format::Yaml);
+        assert_eq!(Format::from_path("/etc/tsplay.conf"), Format::Json);
+    }
+
+
+    #[test]
+    fn json_schema() {
+        let schema = serde_json::to_value(schema()).unwrap();
+        assert_eq!(schema["required"], serde_json::json!(["stream"]));
+
+        let stream = &schema["definitions"]["Stream"];
+        assert!(stream["properties"]["repair_cc"].is_object());
+        assert_eq!(stream["properties"]["filter"]["description"], "PIDs to drop from the output");
+        assert_eq!(stream["properties"]["filter"]["items"]["maximum"], 8191.0);
+
+        let types: Vec<&str> = schema["definitions"]["Type"]["oneOf"].as_array().unwrap().iter()
+            .map(|item| item["properties"]["type"]["enum"][0].as_str().unwrap())
+            .collect();
+        assert_eq!(types, ["file", "udp", "mux"]);
+    }
+}
@@ -0,0 +1,588 @@
+use {
+    std::{
+        env,
+        future::Future,
+        path::{
+            Path,
+            PathBuf,
+        },
+        pin::Pin,
+    },
+
+    tokio::{
+        fs::File,
+        io::AsyncReadExt,
+    },
+    serde_json::{
+        Map,
+        Value,
+    },
+    anyhow::{
+        anyhow,
+        bail,
+        Result,
+        Context,
+    },
+
+    super::Format,
+};
+
+
+/// Maximum depth of the nested includes. Protects from the circular includes
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+
+/// Replaces `${NAME}` and `${NAME:-default}` with values returned by `lookup`.
+/// `$$` is replaced with single `$`.
+pub fn substitute<F>(text: &str, lookup: F) -> Result<String>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(pos) = rest.find('$') {
+        out.push_str(&rest[.. pos]);
+        rest = &rest[pos + 1 ..];
+
+        if let Some(tail) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = tail;
+            continue
+        }
+
+        let tail = match rest.strip_prefix('{') {
+            Some(v) => v,
+            None => {
+                out.push('$');
+                continue
+            }
+        };
+
+        let end = tail.find('}')
+            .ok_or_else(|| anyhow!("unterminated variable \"${{{}\"", tail.lines().next().unwrap_or_default()))?;
+        let expr = &tail[.. end];
+        rest = &tail[end + 1 ..];
+
+        let (name, default) = match expr.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expr, None),
+        };
+
+        match lookup(name).or_else(|| default.map(str::to_owned)) {
+            Some(value) => out.push_str(&value),
+            None => bail!("environment variable \"{}\" is not defined", name),
+        }
+    }
+
+    out.push_str(rest);
+    Ok(out)
+}
+
+
+/// Substitutes variables in the string values of the document.
+/// Values are kept as strings, see [`coerce`] for the numeric options.
+fn substitute_value<F>(value: &mut Value, path: &str, lookup: &F) -> Result<()>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    match value {
+        Value::String(text) if text.contains('$') => {
+            *text = substitute(text, lookup).with_context(|| path.to_owned())?;
+        }
+        Value::Array(list) => {
+            for (i, item) in list.iter_mut().enumerate() {
+                substitute_value(item, &format!("{}[{}]", path, i), lookup)?;
+            }
+        }
+        Value::Object(object) => {
+            for (key, item) in object.iter_mut() {
+                let path = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                substitute_value(item, &path, lookup)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+
+/// Parses document in the given format and substitutes environment variables.
+pub fn parse_value(data: &[u8], format: Format) -> Result<Value> {
+    let text = std::str::from_utf8(data)
+        .context("configuration is not valid UTF-8")?;
+
+    let mut value = match format {
+        Format::Json => serde_json::from_str(text)?,
+        Format::Toml => toml::from_str(text)?,
+        Format::Yaml => serde_yaml::from_str(text)?,
+    };
+
+    substitute_value(&mut value, "", &|name| env::var(name).ok())?;
+
+    Ok(value)
+}
+
+
+/// Merges `value` into `base`. Objects are merged recursively, arrays are concatenated,
+/// other values of the `base` are replaced.
+pub fn merge(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Object(base), Value::Object(value)) => {
+            for (key, value) in value {
+                match base.get_mut(&key) {
+                    Some(item) => merge(item, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Array(value)) => base.extend(value),
+        (base, value) => *base = value,
+    }
+}
+
+
+/// Merges `value` over the template. Unlike `merge()` arrays of the template are replaced.
+fn inherit(template: &Value, value: Value) -> Value {
+    match (template, value) {
+        (Value::Object(template), Value::Object(mut value)) => {
+            let mut out = Map::new();
+            for (key, item) in template {
+                let item = match value.remove(key) {
+                    Some(v) => inherit(item, v),
+                    None => item.clone(),
+                };
+                out.insert(key.clone(), item);
+            }
+            out.extend(value);
+            Value::Object(out)
+        }
+        (_, value) => value,
+    }
+}
+
+
+/// Applies `defaults` and named templates from the `template` object to the streams.
+/// Stream selects template with the `template` field. Options of the stream take precedence
+/// over the template, and options of the template take precedence over the defaults.
+pub fn apply_templates(root: &mut Value) -> Result<()> {
+    let root = match root.as_object_mut() {
+        Some(v) => v,
+        None => return Ok(()),
+    };
+
+    let defaults = root.remove("defaults").unwrap_or_else(|| Value::Object(Map::new()));
+    let templates = match root.remove("template") {
+        Some(Value::Object(v)) => v,
+        Some(_) => bail!("template: expected object with templates by name"),
+        None => Map::new(),
+    };
+
+    let streams = match root.get_mut("stream") {
+        Some(Value::Array(v)) => v,
+        _ => return Ok(()),
+    };
+
+    for (i, stream) in streams.iter_mut().enumerate() {
+        let mut base = defaults.clone();
+
+        if let Some(object) = stream.as_object_mut() {
+            match object.remove("template") {
+                Some(Value::String(name)) => match templates.get(&name) {
+                    Some(template) => base = inherit(&base, template.clone()),
+                    None => bail!("stream[{}].template: template \"{}\" not found", i, name),
+                },
+                Some(_) => bail!("stream[{}].template: expected template name", i),
+                None => {}
+            }
+        }
+
+        *stream = inherit(&base, stream.take());
+    }
+
+    Ok(())
+}
+
+
+/// Returns subschemas of the `schema` with resolved references and combinations.
+fn subschemas<'a>(schema: &'a Value, root: &'a Value, out: &mut Vec<&'a Value>) {
+    if let Some(name) = schema.get("$ref").and_then(Value::as_str) {
+        if let Some(schema) = name.strip_prefix("#/definitions/").and_then(|name| root["definitions"].get(name)) {
+            subschemas(schema, root, out);
+        }
+        return
+    }
+
+    out.push(schema);
+    for key in ["allOf", "anyOf", "oneOf"] {
+        if let Some(list) = schema.get(key).and_then(Value::as_array) {
+            for item in list {
+                subschemas(item, root, out);
+            }
+        }
+    }
+}
+
+
+/// Returns true if any of the subschemas allows values of the given type.
+fn allows(schemas: &[&Value], name: &str) -> bool {
+    schemas.iter().any(|schema| match schema.get("type") {
+        Some(Value::String(v)) => v == name,
+        Some(Value::Array(list)) => list.iter().any(|v| v == name),
+        _ => false,
+    })
+}
+
+
+fn coerce_value(value: &mut Value, schema: &Value, root: &Value) {
+    let mut schemas = Vec::new();
+    subschemas(schema, root, &mut schemas);
+
+    match value {
+        Value::String(text) if ! allows(&schemas, "string") => {
+            *value = match serde_json::from_str(text) {
+                Ok(Value::Number(v)) if v.is_f64() && allows(&schemas, "number") => Value::Number(v),
+                Ok(Value::Number(v)) if ! v.is_f64() && (allows(&schemas, "integer") || allows(&schemas, "number")) => {
+                    Value::Number(v)
+                }
+                Ok(Value::Bool(v)) if allows(&schemas, "boolean") => Value::Bool(v),
+                _ => return,
+            };
+        }
+        Value::Array(list) => {
+            for schema in schemas.iter().filter_map(|schema| schema.get("items")) {
+                for item in list.iter_mut() {
+                    coerce_value(item, schema, root);
+                }
+            }
+        }
+        Value::Object(object) => {
+            for (key, item) in object.iter_mut() {
+                for schema in schemas.iter().filter_map(|schema| schema["properties"].get(key)) {
+                    coerce_value(item, schema, root);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+
+/// Converts strings to numbers and booleans where the JSON Schema of the configuration
+/// does not allow strings. Options like `port` could be defined with the environment variable.
+pub fn coerce(value: &mut Value, schema: &Value) {
+    coerce_value(value, schema, schema)
+}
+
+
+/// Returns list of the included files. Patterns are relative to the `dir`.
+fn include_paths(dir: &Path, include: Value) -> Result<Vec<PathBuf>> {
+    let patterns = match include {
+        Value::String(v) => vec![v],
+        Value::Array(list) => list.into_iter()
+            .map(|item| match item {
+                Value::String(v) => Ok(v),
+                _ => bail!("include: expected path or list of paths"),
+            })
+            .collect::<Result<_>>()?,
+        _ => bail!("include: expected path or list of paths"),
+    };
+
+    let mut paths = Vec::new();
+
+    for pattern in patterns {
+        let pattern = dir.join(pattern);
+        let pattern = pattern.to_str()
+            .ok_or_else(|| anyhow!("include: invalid path {:?}", pattern))?;
+
+        if pattern.contains(['*', '?', '[']) {
+            // glob without matches is not an error: conf.d directory could be empty
+            let mut list = glob::glob(pattern)
+                .with_context(|| format!("include: invalid pattern \"{}\"", pattern))?
+                .collect::<Result<Vec<_>, _>>()?;
+            list.sort();
+            paths.extend(list);
+        } else {
+            paths.push(PathBuf::from(pattern));
+        }
+    }
+
+    Ok(paths)
+}
+
+
+/// Reads configuration file with included files.
+/// Included files are merged in order, then the options of the file itself.
+pub fn load(path: PathBuf, format: Format, depth: usize) -> Pin<Box<dyn Future<Output = Result<Value>> + Send>> {
+    Box::pin(async move {
+        if depth > MAX_INCLUDE_DEPTH {
+            bail!("Too many nested includes in \"{}\". Circular include?", path.display())
+        }
+
+        let mut file = File::open(&path).await
+            .with_context(|| format!("Failed to open configuration file \"{}\"", path.display()))?;
+
+        let mut buf = vec![];
+        file.read_to_end(&mut buf).await
+            .with_context(|| format!("Failed to read configuration file \"{}\"", path.display()))?;
+
+        let mut value = parse_value(&buf, format)
+            .with_context(|| format!("Failed to parse configuration file \"{}\"", path.display()))?;
+
+        let include = match value.as_object_mut().and_then(|object| object.remove("include")) {
+            Some(v) => v,
+            None => return Ok(value),
+        };
+
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        let paths = include_paths(dir, include)
+            .with_context(|| format!("Failed to include files in \"{}\"", path.display()))?;
+
+        let mut out = Value::Object(Map::new());
+        for item in paths {
+            let format = Format::from_path(&item.to_string_lossy());
+            merge(&mut out, load(item, format, depth + 1).await?);
+        }
+        merge(&mut out, value);
+
+        Ok(out)
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        std::{
+            env,
+            fs,
+            path::{
+                Path,
+                PathBuf,
+            },
+            process,
+        },
+
+        serde_json::json,
+
+        super::{
+            substitute,
+            substitute_value,
+            parse_value,
+            apply_templates,
+            coerce,
+            include_paths,
+            load,
+            Format,
+            MAX_INCLUDE_DEPTH,
+        },
+    };
+
+
+    /// Temporary directory removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path = env::temp_dir().join(format!("tsplay-{}-{}", name, process::id()));
+            fs::remove_dir_all(&path).ok();
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+
+        fn write(&self, name: &str, data: &str) -> PathBuf {
+            let path = self.0.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, data).unwrap();
+            path
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+
+    #[test]
+    fn env() {
+        let lookup = |name: &str| match name {
+            "PORT" => Some("1234".to_owned()),
+            _ => None,
+        };
+
+        assert_eq!(substitute("port: ${PORT}", lookup).unwrap(), "port: 1234");
+        assert_eq!(substitute("${ADDR:-127.0.0.1}:${PORT}", lookup).unwrap(), "127.0.0.1:1234");
+        assert_eq!(substitute("$$PORT $PORT", lookup).unwrap(), "$PORT $PORT");
+        assert!(substitute("${ADDR}", lookup).is_err());
+        assert!(substitute("${PORT", lookup).is_err());
+    }
+
+
+    #[test]
+    fn env_value() {
+        let lookup = |name: &str| match name {
+            "PORT" => Some("1234".to_owned()),
+            "NAME" => Some(r#"a", "port": 1"#.to_owned()),
+            _ => None,
+        };
+
+        let mut value = json!({
+            "stream": [ { "name": "${NAME}", "port": "${PORT}", "path": "/${PORT}.ts" } ],
+            "tag": "${ADDR:-true}",
+        });
+        substitute_value(&mut value, "", &lookup).unwrap();
+        assert_eq!(value, json!({
+            "stream": [ { "name": r#"a", "port": 1"#, "port": "1234", "path": "/1234.ts" } ],
+            "tag": "true",
+        }));
+
+        let mut value = json!({ "stream": [ { "name": "${ADDR}" } ] });
+        let err = substitute_value(&mut value, "", &lookup).unwrap_err();
+        assert_eq!(err.to_string(), "stream[0].name");
+
+        // variables in comments are not substituted
+        let value = parse_value(b"# ${TSPLAY_UNDEFINED}\nname = \"a\"\n", Format::Toml).unwrap();
+        assert_eq!(value, json!({ "name": "a" }));
+    }
+
+
+    #[test]
+    fn templates() {
+        let mut config = json!({
+            "defaults": { "repair_cc": true, "filter": [ 18 ] },
+            "template": {
+                "udp": { "output": { "type": "udp", "address": "239.0.0.1", "port": 10000 } },
+            },
+            "stream": [
+                { "name": "a", "template": "udp", "output": { "port": 10001 }, "filter": [ 20 ] },
+                { "name": "b", "repair_cc": false },
+            ],
+        });
+
+        apply_templates(&mut config).unwrap();
+        assert_eq!(config, json!({
+            "stream": [
+                {
+                    "name": "a",
+                    "repair_cc": true,
+                    "filter": [ 20 ],
+                    "output": { "type": "udp", "address": "239.0.0.1", "port": 10001 },
+                },
+                { "name": "b", "repair_cc": false, "filter": [ 18 ] },
+            ],
+        }));
+
+        let mut config = json!({ "stream": [ { "name": "a", "template": "x" } ] });
+        assert_eq!(
+            apply_templates(&mut config).unwrap_err().to_string(),
+            "stream[0].template: template \"x\" not found",
+        );
+    }
+
+
+    #[test]
+    fn includes() {
+        let dir = TempDir::new("include-paths");
+        dir.write("conf.d/b.json", "{}");
+        dir.write("conf.d/a.json", "{}");
+        dir.write("conf.d/c.txt", "");
+
+        let paths = include_paths(&dir.0, json!([ "base.json", "conf.d/*.json" ])).unwrap();
+        assert_eq!(paths, vec![
+            dir.0.join("base.json"),
+            dir.0.join("conf.d/a.json"),
+            dir.0.join("conf.d/b.json"),
+        ]);
+
+        assert!(include_paths(&dir.0, json!("empty/*.json")).unwrap().is_empty());
+        assert!(include_paths(&dir.0, json!(1)).is_err());
+        assert!(include_paths(Path::new("."), json!([ "a", 1 ])).is_err());
+    }
+
+
+    #[tokio::test]
+    async fn load_includes() {
+        let dir = TempDir::new("load");
+        dir.write("conf.d/b.toml", "[[stream]]\nname = \"b\"\n\n[api]\nport = 8081\n");
+        dir.write("conf.d/a.yaml", "stream:\n  - name: a\napi:\n  port: 8080\n");
+        let path = dir.write("main.json", r#"{
+            "include": "conf.d/*",
+            "stream": [ { "name": "c" } ],
+            "api": { "address": "127.0.0.1" }
+        }"#);
+
+        let value = load(path, Format::Json, 0).await.unwrap();
+        assert_eq!(value, json!({
+            "stream": [ { "name": "a" }, { "name": "b" }, { "name": "c" } ],
+            "api": { "address": "127.0.0.1", "port": 8081 },
+        }));
+
+        let path = dir.write("missing.json", r#"{ "include": "none.json" }"#);
+        let err = load(path, Format::Json, 0).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Failed to open configuration file"));
+    }
+
+
+    #[tokio::test]
+    async fn circular_include() {
+        let dir = TempDir::new("circular");
+        dir.write("a.json", r#"{ "include": "b.json" }"#);
+        let path = dir.write("b.json", r#"{ "include": "a.json" }"#);
+
+        let err = load(path, Format::Json, 0).await.unwrap_err();
+        assert!(format!("{:#}", err).contains("Circular include?"));
+
+        // nesting up to the limit is allowed
+        for i in 0 .. MAX_INCLUDE_DEPTH {
+            dir.write(&format!("{}.json", i), &format!(r#"{{ "include": "{}.json" }}"#, i + 1));
+        }
+        dir.write(&format!("{}.json", MAX_INCLUDE_DEPTH), r#"{ "x": 1 }"#);
+        let value = load(dir.0.join("0.json"), Format::Json, 0).await.unwrap();
+        assert_eq!(value, json!({ "x": 1 }));
+    }
+
+
+    #[test]
+    fn coerce_schema() {
+        let schema = serde_json::to_value(crate::config::schema()).unwrap();
+        let lookup = |name: &str| match name {
+            "CH" => Some("101".to_owned()),
+            "PORT" => Some("1234".to_owned()),
+            _ => None,
+        };
+
+        let mut value = json!({
+            "stream": [
+                {
+                    "name": "${CH}",
+                    "input": { "type": "file", "path": "${CH}" },
+                    "output": { "type": "udp", "address": "239.0.0.1", "port": "${PORT}" },
+                    "program": { "pnr": "${CH}" },
+                    "filter": [ "${CH}" ],
+                    "bitrate": "${PORT}",
+                    "loop": "true",
+                },
+            ],
+        });
+        substitute_value(&mut value, "", &lookup).unwrap();
+        coerce(&mut value, &schema);
+        assert_eq!(value, json!({
+            "stream": [
+                {
+                    "name": "101",
+                    "input": { "type": "file", "path": "101" },
+                    "output": { "type": "udp", "address": "239.0.0.1", "port": 1234 },
+                    "program": { "pnr": 101 },
+                    "filter": [ 101 ],
+                    "bitrate": 1234,
+                    "loop": true,
+                },
+            ],
+        }));
+    }
+}
@@ -0,0 +1,376 @@
+use {
+    std::{
+        fmt,
+        net::IpAddr,
+        collections::HashMap,
+    },
+
+    crate::ts::NULL_PID,
+
+    super::{
+        Api,
+        Config,
+        Mux,
+        OutputError,
+        Stream,
+        Type,
+        STALL_TIMEOUT,
+    },
+};
+
+
+/// Maximum value of the 13-bit PID
+const PID_MAX: u16 = 0x1FFF;
+/// PIDs below are reserved for the PSI and SI tables
+const PID_RESERVED: u16 = 0x0020;
+
+
+/// Single problem in the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    /// JSON path of the value. For example: `stream[3].output.port`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", &self.path, &self.message)
+    }
+}
+
+
+/// All problems found in the configuration.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationError(pub Vec<Problem>);
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, problem) in self.0.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+
+#[derive(Default)]
+struct Validator {
+    problems: Vec<Problem>,
+}
+
+impl Validator {
+    fn report<P: fmt::Display, M: ToString>(&mut self, path: P, message: M) {
+        self.problems.push(Problem {
+            path: path.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    fn check_name(&mut self, path: &str, name: &str) {
+        if name.is_empty() {
+            self.report(format_args!("{}.name", path), "name is empty");
+        }
+    }
+
+    /// Checks that address could be used with IPv4 UDP socket.
+    fn check_host(&mut self, path: &str, address: &str) {
+        match address.parse::<IpAddr>() {
+            Ok(IpAddr::V4(ip)) if ip.is_unspecified() => {
+                self.report(path, "unspecified address could not be used")
+            }
+            Ok(IpAddr::V4(_)) => {}
+            Ok(IpAddr::V6(_)) => self.report(path, "IPv6 address is not supported"),
+            Err(_) => {
+                let valid = ! address.is_empty()
+                    && address.len() <= 253
+                    && address.split('.').all(|label| {
+                        ! label.is_empty()
+                            && label.len() <= 63
+                            && ! label.starts_with('-')
+                            && ! label.ends_with('-')
+                            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                    });
+                if ! valid {
+                    self.report(path, format_args!("invalid address \"{}\"", address))
+                }
+            }
+        }
+    }
+
+    fn check_port(&mut self, path: &str, port: u16) {
+        if port == 0 {
+            self.report(path, "port should be in range 1-65535");
+        }
+    }
+
+    fn check_pid(&mut self, path: &str, pid: u16) {
+        if pid > PID_MAX {
+            self.report(path, format_args!("PID {} is out of range 0-{}", pid, PID_MAX));
+        }
+    }
+
+    /// Checks input or output of the stream or multiplexer.
+    /// `mux` is a list of known multiplexers, `None` if multiplexer could not be used.
+    fn check_type(&mut self, path: &str, value: &Type, mux: Option<&HashMap<&str, usize>>) {
+        match value {
+            Type::File { path: file } => {
+                if file.is_empty() {
+                    self.report(format_args!("{}.path", path), "path is empty");
+                }
+            }
+            Type::Udp { address, port } => {
+                self.check_host(&format!("{}.address", path), address);
+                self.check_port(&format!("{}.port", path), *port);
+            }
+            Type::Mux { name } => match mux {
+                None => self.report(path, "multiplexer could not be used here"),
+                Some(mux) if ! mux.contains_key(name.as_str()) => {
+                    self.report(format_args!("{}.name", path), format_args!("multiplexer \"{}\" not found", name))
+                }
+                Some(_) => {}
+            },
+        }
+    }
+
+    fn check_stream(&mut self, path: &str, stream: &Stream, mux: &HashMap<&str, usize>) {
+        self.check_name(path, &stream.name);
+        self.check_type(&format!("{}.input", path), &stream.input, None);
+        self.check_type(&format!("{}.output", path), &stream.output, Some(mux));
+
+        if let Some(program) = &stream.program {
+            if program.pnr == Some(0) {
+                self.report(format_args!("{}.program.pnr", path), "program_number 0 is reserved for the NIT");
+            }
+        }
+
+        for (i, &pid) in stream.filter.iter().enumerate() {
+            self.check_pid(&format!("{}.filter[{}]", path, i), pid);
+        }
+
+        let mut remap = HashMap::new();
+        let mut targets = HashMap::new();
+        for (i, item) in stream.remap.iter().enumerate() {
+            let item_path = format!("{}.remap[{}]", path, i);
+            self.check_pid(&format!("{}.from", &item_path), item.from);
+            self.check_pid(&format!("{}.to", &item_path), item.to);
+            if item.to < PID_RESERVED || item.to == NULL_PID {
+                self.report(
+                    format_args!("{}.to", &item_path),
+                    format_args!("PID {} is reserved and could not be used as a target", item.to),
+                );
+            }
+            if let Some(first) = remap.insert(item.from, i) {
+                self.report(
+                    format_args!("{}.from", &item_path),
+                    format_args!("PID {} is already remapped in {}.remap[{}]", item.from, path, first),
+                );
+            }
+            if let Some(first) = targets.insert(item.to, i) {
+                self.report(
+                    format_args!("{}.to", &item_path),
+                    format_args!("PID {} is already a target of {}.remap[{}]", item.to, path, first),
+                );
+            }
+        }
+
+        if stream.bitrate == Some(0) {
+            self.report(format_args!("{}.bitrate", path), "bitrate should be greater than 0");
+        }
+        if stream.strip_null && stream.bitrate.is_some() {
+            self.report(
+                format_args!("{}.strip_null", path),
+                "null packets could not be removed from the stream with constant bitrate",
+            );
+        }
+
+        self.check_output_error(path, &stream.output_error);
+    }
+
+    fn check_output_error(&mut self, path: &str, policy: &OutputError) {
+        if policy.max_delay < policy.delay {
+            self.report(format_args!("{}.output_error.max_delay", path), "max_delay should not be less than delay");
+        }
+        if policy.max_delay >= STALL_TIMEOUT {
+            self.report(
+                format_args!("{}.output_error.max_delay", path),
+                format_args!("max_delay should be less than {}ms", STALL_TIMEOUT),
+            );
+        }
+    }
+
+    fn check_mux(&mut self, path: &str, mux: &Mux) {
+        self.check_name(path, &mux.name);
+        self.check_type(&format!("{}.output", path), &mux.output, None);
+
+        if mux.bitrate == 0 {
+            self.report(format_args!("{}.bitrate", path), "bitrate should be greater than 0");
+        }
+
+        let interval = &mux.psi_interval;
+        for (name, value) in [("pat", interval.pat), ("pmt", interval.pmt), ("sdt", interval.sdt), ("nit", interval.nit)] {
+            if value == 0 {
+                self.report(format_args!("{}.psi_interval.{}", path, name), "interval should be greater than 0");
+            }
+        }
+
+        self.check_output_error(path, &mux.output_error);
+    }
+
+    fn check_api(&mut self, api: &Api) {
+        match api.address.parse::<IpAddr>() {
+            Ok(_) => {}
+            Err(_) => self.report("api.address", format_args!("invalid address \"{}\"", &api.address)),
+        }
+        self.check_port("api.port", api.port);
+    }
+
+    fn check_config(&mut self, config: &Config) {
+        let mut mux = HashMap::new();
+        for (i, item) in config.mux.iter().enumerate() {
+            let path = format!("mux[{}]", i);
+            self.check_mux(&path, item);
+            if let Some(first) = mux.insert(item.name.as_str(), i) {
+                self.report(
+                    format_args!("{}.name", &path),
+                    format_args!("duplicate name \"{}\", already defined in mux[{}]", &item.name, first),
+                );
+            }
+        }
+
+        if config.stream.is_empty() {
+            self.report("stream", "at least one stream should be defined");
+        }
+
+        let mut names = HashMap::new();
+        for (i, stream) in config.stream.iter().enumerate() {
+            let path = format!("stream[{}]", i);
+            self.check_stream(&path, stream, &mux);
+            if let Some(first) = names.insert(stream.name.as_str(), i) {
+                self.report(
+                    format_args!("{}.name", &path),
+                    format_args!("duplicate name \"{}\", already defined in stream[{}]", &stream.name, first),
+                );
+            }
+        }
+
+        if let Some(api) = &config.api {
+            self.check_api(api);
+        }
+    }
+
+    fn finish(self) -> Result<(), ValidationError> {
+        if self.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError(self.problems))
+        }
+    }
+}
+
+
+impl Config {
+    /// Checks the configuration. Returns all found problems.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let mut validator = Validator::default();
+        validator.check_config(self);
+        validator.finish()
+    }
+
+    /// Checks the stream definition before adding to this configuration.
+    pub fn validate_stream(&self, stream: &Stream) -> Result<(), ValidationError> {
+        let mux = self.mux.iter()
+            .enumerate()
+            .map(|(i, item)| (item.name.as_str(), i))
+            .collect();
+
+        let mut validator = Validator::default();
+        validator.check_stream("stream", stream, &mux);
+        validator.finish()
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::config::Config,
+
+        super::Problem,
+    };
+
+
+    #[test]
+    fn problems() {
+        let config: Config = serde_json::from_str(r#"{
+            "mux": [
+                {
+                    "name": "m",
+                    "output": { "type": "udp", "address": "239.0.0.1", "port": 1234 },
+                    "bitrate": 0,
+                    "psi_interval": { "pmt": 0 }
+                }
+            ],
+            "stream": [
+                {
+                    "name": "a",
+                    "input": { "type": "file", "path": "a.ts" },
+                    "output": { "type": "udp", "address": "239.0.0.1", "port": 1234 }
+                },
+                {
+                    "name": "a",
+                    "input": { "type": "udp", "address": "::1", "port": 1234 },
+                    "output": { "type": "mux", "name": "x" },
+                    "remap": [ { "from": 256, "to": 8192 } ]
+                },
+                {
+                    "name": "c",
+                    "input": { "type": "file", "path": "c.ts" },
+                    "output": { "type": "udp", "address": "bad host", "port": 0 },
+                    "output_error": { "max_delay": 10000 }
+                },
+                {
+                    "name": "d",
+                    "input": { "type": "file", "path": "d.ts" },
+                    "output": { "type": "udp", "address": "239.0.0.1", "port": 1234 },
+                    "remap": [
+                        { "from": 256, "to": 512 },
+                        { "from": 257, "to": 512 },
+                        { "from": 258, "to": 16 },
+                        { "from": 259, "to": 8191 }
+                    ],
+                    "strip_null": true,
+                    "bitrate": 1000000
+                }
+            ]
+        }"#).unwrap();
+
+        let problems: Vec<String> = config.validate().unwrap_err().0.iter()
+            .map(Problem::to_string)
+            .collect();
+
+        assert_eq!(problems, vec![
+            "mux[0].bitrate: bitrate should be greater than 0",
+            "mux[0].psi_interval.pmt: interval should be greater than 0",
+            "stream[1].input.address: IPv6 address is not supported",
+            "stream[1].output.name: multiplexer \"x\" not found",
+            "stream[1].remap[0].to: PID 8192 is out of range 0-8191",
+            "stream[1].name: duplicate name \"a\", already defined in stream[0]",
+            "stream[2].output.address: invalid address \"bad host\"",
+            "stream[2].output.port: port should be in range 1-65535",
+            "stream[2].output_error.max_delay: max_delay should be less than 10000ms",
+            "stream[3].remap[1].to: PID 512 is already a target of stream[3].remap[0]",
+            "stream[3].remap[2].to: PID 16 is reserved and could not be used as a target",
+            "stream[3].remap[3].to: PID 8191 is reserved and could not be used as a target",
+            "stream[3].strip_null: null packets could not be removed from the stream with constant bitrate",
+        ]);
+
+        let config: Config = serde_json::from_str(r#"{ "stream": [] }"#).unwrap();
+        assert_eq!(config.validate().unwrap_err().to_string(), "stream: at least one stream should be defined");
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    fmt,
+    io,
+};
+
+
+/// Errors of the packet parsing, stream I/O and the application.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Packet is not started with the sync byte. Contains the first byte
+    Sync(u8),
+    /// PES packet is not started with the packet_start_code_prefix
+    StartCode,
+    /// Buffer is shorter than expected
+    Size {
+        /// Required size in bytes
+        expected: usize,
+        /// Actual size in bytes
+        len: usize,
+    },
+    /// Failed to open input or output
+    Open {
+        /// URL of the input or output
+        endpoint: String,
+        /// I/O error
+        source: io::Error,
+    },
+    /// Failed to read from the input
+    Read {
+        /// URL of the input or output
+        endpoint: String,
+        /// I/O error
+        source: io::Error,
+    },
+    /// Failed to write to the output
+    Write {
+        /// URL of the input or output
+        endpoint: String,
+        /// I/O error
+        source: io::Error,
+    },
+    /// Multiplexer with given name is not defined
+    MuxNotFound(String),
+    /// Multiplexer could be used as the stream output only
+    MuxUsage(String),
+    /// Failed to load configuration file
+    Config {
+        /// Path of the configuration file
+        path: String,
+        /// Parsing or validation error
+        source: Box<dyn std::error::Error + Send + Sync>,
+    },
+    /// Failed to install signal handler
+    Signal(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Sync(byte) => write!(f, "packet starts with {:#04x} instead of sync byte 0x47", byte),
+            Error::StartCode => write!(f, "PES packet_start_code_prefix not found"),
+            Error::Size { expected, len } => write!(f, "expected {} bytes, got {}", expected, len),
+            Error::Open { endpoint, source } => write!(f, "failed to open {}: {}", endpoint, source),
+            Error::Read { endpoint, source } => write!(f, "failed to read from {}: {}", endpoint, source),
+            Error::Write { endpoint, source } => write!(f, "failed to write to {}: {}", endpoint, source),
+            Error::MuxNotFound(name) => write!(f, "multiplexer \"{}\" not found", name),
+            Error::MuxUsage(name) => write!(f, "multiplexer \"{}\" could be used as stream output only", name),
+            Error::Config { path, .. } => write!(f, "failed to load configuration file \"{}\"", path),
+            Error::Signal(source) => write!(f, "failed to install signal handler: {}", source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Open { source, .. } => Some(source),
+            Error::Read { source, .. } => Some(source),
+            Error::Write { source, .. } => Some(source),
+            Error::Signal(source) => Some(source),
+            Error::Config { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
@@ -1,2 +1,25 @@
 mod packet;
-pub use packet::PesPacket;
+pub use packet::{
+    PesPacket,
+    PesHeader,
+};
+
+mod packetizer;
+pub use packetizer::Packetizer;
+
+
+/// Value out of the 33-bit PTS range, used as undefined PTS
+pub const PTS_NONE: u64 = 1 << 33;
+/// PTS wraps around after 2^33 clocks of 90kHz
+pub const PTS_MAX: u64 = PTS_NONE - 1;
+
+
+/// Returns difference between previous PTS and current PTS
+#[inline]
+pub fn pts_delta(last_pts: u64, current_pts: u64) -> u64 {
+    if current_pts >= last_pts {
+        current_pts - last_pts
+    } else {
+        current_pts + PTS_MAX - last_pts
+    }
+}
@@ -1,15 +1,68 @@
-use crate::ts::TsPacket;
+use {
+    std::convert::TryFrom,
 
+    crate::{
+        ts::TsPacket,
+        error::Error,
+    },
+};
 
+
+/// Size of the packet_start_code_prefix, stream_id and PES_packet_length
+const PES_HEADER_SIZE: usize = 6;
+
+
+/// Reads 33-bit timestamp coded in 5 bytes with marker bits.
+#[inline]
+fn get_timestamp(data: &[u8]) -> u64 {
+    (u64::from(data[0] & 0x0E) << 29) |
+    (u64::from(data[1]       ) << 22) |
+    (u64::from(data[2] & 0xFE) << 14) |
+    (u64::from(data[3]       ) <<  7) |
+    (u64::from(data[4]       ) >>  1)
+}
+
+
+/// Appends 33-bit timestamp with 4-bit `prefix` and marker bits.
+#[inline]
+fn push_timestamp(prefix: u8, value: u64, out: &mut Vec<u8>) {
+    out.extend_from_slice(&[
+        (prefix << 4) | ((value >> 29) as u8 & 0x0E) | 0x01,
+        (value >> 22) as u8,
+        ((value >> 14) as u8 & 0xFE) | 0x01,
+        (value >> 7) as u8,
+        ((value << 1) as u8 & 0xFE) | 0x01,
+    ]);
+}
+
+
+/// Zero-copy view of the PES packet header.
+/// ISO/IEC 13818-1 : 2.4.3.6 PES packet
 pub struct PesPacket<'a> {
     pes: &'a [u8]
 }
 
 impl<'a> PesPacket<'a> {
-    pub fn new(pes: &'a [u8]) -> Self {
-        Self { pes }
+    /// Wraps buffer started with the packet_start_code_prefix.
+    pub fn new(pes: &'a [u8]) -> Result<Self, Error> {
+        if pes.len() < PES_HEADER_SIZE {
+            return Err(Error::Size { expected: PES_HEADER_SIZE, len: pes.len() })
+        }
+
+        if pes[.. 3] != [0x00, 0x00, 0x01] {
+            return Err(Error::StartCode)
+        }
+
+        Ok(Self { pes })
     }
 
+    /// Returns stream_id
+    #[inline]
+    pub fn get_stream_id(&self) -> u8 {
+        self.pes[3]
+    }
+
+    /// Returns true if stream_id has optional PES header
     #[inline]
     pub fn is_syntax_spec(&self) -> bool {
         match self.pes.get(3) {
@@ -30,6 +83,7 @@ impl<'a> PesPacket<'a> {
         }
     }
 
+    /// Returns true if PTS_DTS_flags has PTS
     #[inline]
     pub fn is_pts(&self) -> bool {
         match self.pes.get(7) {
@@ -38,28 +92,122 @@ impl<'a> PesPacket<'a> {
         }
     }
 
+    /// Returns 33-bit PTS in 90kHz clocks
     #[inline]
     pub fn get_pts(&self) -> Option<u64> {
         if ! (self.is_pts() & self.is_syntax_spec()) {
             return None
         }
 
-        self.pes.get(9 .. 14).map(
-            |pts|
-            {
-                (u64::from(pts[0] & 0x0E) << 29) |
-                (u64::from(pts[1]       ) << 22) |
-                (u64::from(pts[2] & 0xFE) << 14) |
-                (u64::from(pts[3]       ) <<  7) |
-                (u64::from(pts[4]       ) >>  1)
+        self.pes.get(9 .. 14).map(get_timestamp)
+    }
+
+    /// Returns 33-bit DTS in 90kHz clocks
+    #[inline]
+    pub fn get_dts(&self) -> Option<u64> {
+        if ! (self.is_pts() & self.is_syntax_spec()) {
+            return None
+        }
+
+        match self.pes.get(7) {
+            Some(byte) if (byte & 0x40) != 0 => self.pes.get(14 .. 19).map(get_timestamp),
+            _ => None,
+        }
+    }
+
+    /// Returns data after the PES header. Could be shorter than the PES packet
+    /// if packet is not complete.
+    #[inline]
+    pub fn get_payload(&self) -> &'a [u8] {
+        let offset = if self.is_syntax_spec() {
+            match self.pes.get(8) {
+                Some(&size) => 9 + usize::from(size),
+                None => self.pes.len(),
             }
-        )
+        } else {
+            PES_HEADER_SIZE
+        };
+
+        self.pes.get(offset ..).unwrap_or(&[])
+    }
+}
+
+
+/// Fields of the PES packet header.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct PesHeader {
+    /// stream_id, e.g. 0xE0 for the first video stream
+    pub stream_id: u8,
+    /// 33-bit PTS in 90kHz clocks
+    pub pts: Option<u64>,
+    /// 33-bit DTS in 90kHz clocks. Not coded without PTS or if equal to PTS
+    pub dts: Option<u64>,
+}
+
+impl PesHeader {
+    /// Builds complete PES packet with header and `data`.
+    /// PES_packet_length is set to 0 if packet is longer than 65535 bytes,
+    /// which is allowed for video streams only.
+    pub fn build(&self, data: &[u8]) -> Vec<u8> {
+        let mut pes = Vec::with_capacity(PES_HEADER_SIZE + 3 + 10 + data.len());
+        pes.extend_from_slice(&[0x00, 0x00, 0x01, self.stream_id, 0x00, 0x00]);
+
+        let syntax_spec = PesPacket { pes: &pes }.is_syntax_spec();
+        if syntax_spec {
+            let dts = self.pts.and(self.dts).filter(|&dts| Some(dts) != self.pts);
+            let (flags, size) = match (self.pts, dts) {
+                (Some(_), Some(_)) => (0xC0, 10),
+                (Some(_), None) => (0x80, 5),
+                _ => (0x00, 0),
+            };
+
+            pes.extend_from_slice(&[0x80, flags, size]);
+            if let Some(pts) = self.pts {
+                push_timestamp(if dts.is_some() { 0x03 } else { 0x02 }, pts, &mut pes);
+            }
+            if let Some(dts) = dts {
+                push_timestamp(0x01, dts, &mut pes);
+            }
+        }
+
+        pes.extend_from_slice(data);
+
+        let length = pes.len() - PES_HEADER_SIZE;
+        if length <= 0xFFFF {
+            pes[4 .. 6].copy_from_slice(&(length as u16).to_be_bytes());
+        }
+
+        pes
     }
 }
 
 
-impl<'a> From<TsPacket<'a>> for PesPacket<'a> {
-    fn from(ts: TsPacket<'a>) -> Self {
+impl<'a> TryFrom<TsPacket<'a>> for PesPacket<'a> {
+    type Error = Error;
+
+    fn try_from(ts: TsPacket<'a>) -> Result<Self, Error> {
         Self::new(ts.get_payload())
     }
 }
+
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::error::Error,
+
+        super::PesPacket,
+    };
+
+
+    #[test]
+    fn new() {
+        assert!(matches!(PesPacket::new(&[0x00, 0x00, 0x01]), Err(Error::Size { expected: 6, len: 3 })));
+        assert!(matches!(PesPacket::new(&[0x00, 0x00, 0x02, 0xE0, 0x00, 0x00]), Err(Error::StartCode)));
+
+        let pes = PesPacket::new(&[
+            0x00, 0x00, 0x01, 0xE0, 0x00, 0x00, 0x80, 0x80, 0x05, 0x21, 0x00, 0x01, 0x00, 0x01,
+        ]).unwrap();
+        assert_eq!(pes.get_pts(), Some(0));
+    }
+}
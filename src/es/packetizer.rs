@@ -0,0 +1,108 @@
+use crate::ts::TsPacketMut;
+
+
+/// Splits PES packets of the elementary stream into TS packets.
+///
+/// First packet of each PES packet has payload_unit_start_indicator, the last one is padded
+/// with adaptation field stuffing. Continuity counter is incremented for each packet with payload.
+pub struct Packetizer {
+    pid: u16,
+    cc: u8,
+}
+
+impl Packetizer {
+    /// Creates packetizer for the elementary stream with given PID.
+    pub fn new(pid: u16) -> Self {
+        Self {
+            pid,
+            cc: 0,
+        }
+    }
+
+    /// Appends TS packets with the `pes` to the `out`.
+    /// PCR and random_access_indicator are set in the first packet if defined.
+    pub fn push(&mut self, pes: &[u8], pcr: Option<u64>, random_access: bool, out: &mut Vec<u8>) {
+        let mut data = pes;
+        let mut first = true;
+
+        while ! data.is_empty() {
+            let mut ts = TsPacketMut::push(out);
+            ts.set_pid(self.pid);
+            ts.set_cc(self.cc);
+            self.cc = (self.cc + 1) & 0x0F;
+
+            if first {
+                first = false;
+                ts.set_pusi(true);
+                ts.set_random_access(random_access);
+                ts.set_pcr(pcr);
+            }
+
+            let size = ts.set_payload(data);
+            data = &data[size ..];
+        }
+    }
+
+    /// Appends packet with PCR only. Used for PCR on the PID without elementary stream,
+    /// or to keep PCR interval between PES packets.
+    pub fn push_pcr(&mut self, pcr: u64, out: &mut Vec<u8>) {
+        let mut ts = TsPacketMut::push(out);
+        ts.set_pid(self.pid);
+        // not incremented for packets without payload
+        ts.set_cc(self.cc.wrapping_sub(1) & 0x0F);
+        ts.set_pcr(Some(pcr));
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::{
+            ts::TsIter,
+            es::{
+                PesPacket,
+                PesHeader,
+            },
+        },
+
+        super::Packetizer,
+    };
+
+
+    #[test]
+    fn packetize() {
+        let data: Vec<u8> = (0 .. 1000).map(|i| i as u8).collect();
+        let pes = PesHeader { stream_id: 0xE0, pts: Some(1 << 32), dts: Some(90_000) }.build(&data);
+
+        let mut out = Vec::new();
+        let mut packetizer = Packetizer::new(0x100);
+        packetizer.push(&pes, Some(27_000_000), true, &mut out);
+        packetizer.push_pcr(27_100_000, &mut out);
+
+        let packets: Vec<_> = TsIter::new(&out).collect();
+        assert_eq!(packets.len(), 7);
+
+        let cc: Vec<u8> = packets.iter().map(|ts| ts.get_cc()).collect();
+        assert_eq!(cc, vec![0, 1, 2, 3, 4, 5, 5]);
+        assert!(packets.iter().all(|ts| ts.get_pid() == 0x100));
+
+        let first = &packets[0];
+        assert!(first.is_pusi() && first.is_random_access());
+        assert_eq!(first.get_pcr(), Some(27_000_000));
+        assert!(packets[1 ..].iter().all(|ts| ! ts.is_pusi()));
+
+        let last = &packets[6];
+        assert_eq!(last.get_pcr(), Some(27_100_000));
+        assert!(! last.is_payload());
+
+        let payload: Vec<u8> = packets.iter().flat_map(|ts| ts.get_payload().iter().copied()).collect();
+        assert_eq!(payload, pes);
+
+        let pes = PesPacket::new(&payload).unwrap();
+        assert_eq!(pes.get_stream_id(), 0xE0);
+        assert_eq!(pes.get_pts(), Some(1 << 32));
+        assert_eq!(pes.get_dts(), Some(90_000));
+        assert_eq!(pes.get_payload(), &data[..]);
+    }
+}
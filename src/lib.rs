@@ -0,0 +1,52 @@
+//! MPEG-TS playout engine.
+//!
+//! - [`ts`] and [`es`] - zero-copy parsers of the TS and PES packets
+//! - [`streams`] - asynchronous inputs and outputs: file, UDP and multiplexer
+//! - [`Application`] - playout engine launching streams from the configuration file
+//! - [`probe()`] - offline analysis of the TS files
+//!
+//! ```no_run
+//! use tsplay::Application;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let mut app = Application::new("/etc/tsplay.conf", None).await?;
+//! app.run().await?;
+//! # Ok(())
+//! # }
+//! ```
+
+#![warn(missing_docs)]
+
+/// MPEG-TS packets
+pub mod ts;
+/// PES packets
+pub mod es;
+mod psi;
+mod misc;
+mod error;
+mod stats;
+mod config;
+/// Inputs and outputs of the streams
+pub mod streams;
+mod pipeline;
+mod monitor;
+mod probe;
+mod api;
+mod metrics;
+mod notify;
+mod mux;
+mod application;
+
+pub use {
+    application::Application,
+    config::{
+        Format,
+        Log as LogConfig,
+        LogFormat,
+    },
+    error::Error,
+    probe::{
+        probe,
+        Report,
+    },
+};
@@ -0,0 +1,335 @@
+use {
+    std::{
+        io::{
+            self,
+            Write,
+        },
+        fmt::Write as _,
+        os::unix::net::UnixDatagram,
+        sync::{
+            OnceLock,
+            RwLock,
+        },
+        time::{
+            SystemTime,
+            UNIX_EPOCH,
+        },
+    },
+
+    log::{
+        kv,
+        Level,
+        LevelFilter,
+        Log,
+        Metadata,
+        Record,
+    },
+    serde_json::{
+        Map,
+        Value,
+    },
+
+    tsplay::{
+        LogConfig,
+        LogFormat,
+    },
+};
+
+
+const SYSLOG_SOCKET: &str = "/dev/log";
+const JOURNALD_SOCKET: &str = "/run/systemd/journal/socket";
+const IDENTIFIER: &str = env!("CARGO_PKG_NAME");
+
+
+/// Options from the command line. Overrides options from the configuration.
+#[derive(Clone, Copy)]
+struct Options {
+    format: Option<LogFormat>,
+    debug: bool,
+}
+
+
+struct Logger {
+    options: RwLock<Options>,
+    format: RwLock<LogFormat>,
+    /// Socket to send messages to the syslog or journald. Opened on the first message
+    socket: OnceLock<Option<UnixDatagram>>,
+}
+
+impl Logger {
+    /// Sends datagram to the local socket. Returns false if socket is not available.
+    /// Socket is non-blocking, message is dropped if the receiver queue is full.
+    fn send(&self, path: &str, data: &[u8]) -> bool {
+        let socket = self.socket.get_or_init(|| {
+            let socket = UnixDatagram::unbound().ok()?;
+            socket.set_nonblocking(true).ok()?;
+            Some(socket)
+        });
+
+        match socket {
+            Some(socket) => match socket.send_to(data, path) {
+                Ok(_) => true,
+                Err(err) => err.kind() == io::ErrorKind::WouldBlock,
+            },
+            None => false,
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if ! self.enabled(record.metadata()) {
+            return
+        }
+
+        let format = *self.format.read().unwrap_or_else(|err| err.into_inner());
+        let sent = match format {
+            LogFormat::Text => false,
+            LogFormat::Json => {
+                eprintln!("{}", format_json(record));
+                true
+            }
+            LogFormat::Syslog => self.send(SYSLOG_SOCKET, format_syslog(record).as_bytes()),
+            LogFormat::Journald => self.send(JOURNALD_SOCKET, &format_journald(record)),
+        };
+
+        if ! sent {
+            eprintln!("{}", format_text(record));
+        }
+    }
+
+    fn flush(&self) {
+        std::io::stderr().flush().ok();
+    }
+}
+
+
+static LOGGER: Logger = Logger {
+    options: RwLock::new(Options { format: None, debug: false }),
+    format: RwLock::new(LogFormat::Text),
+    socket: OnceLock::new(),
+};
+
+
+/// Collects key-values of the record.
+struct Fields(Vec<(String, String)>);
+
+impl<'kvs> kv::VisitSource<'kvs> for Fields {
+    fn visit_pair(&mut self, key: kv::Key<'kvs>, value: kv::Value<'kvs>) -> Result<(), kv::Error> {
+        self.0.push((key.to_string(), value.to_string()));
+        Ok(())
+    }
+}
+
+fn fields(record: &Record) -> Vec<(String, String)> {
+    let mut fields = Fields(Vec::new());
+    record.key_values().visit(&mut fields).ok();
+    fields.0
+}
+
+
+/// Returns current time in the RFC 3339 format in UTC.
+fn timestamp() -> String {
+    let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = time.as_secs();
+    let days = (secs / 86400) as i64;
+
+    // civil date from the days since 1970-01-01
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60,
+        time.subsec_millis(),
+    )
+}
+
+
+fn format_text(record: &Record) -> String {
+    let mut out = format!("{} {:5} {}", timestamp(), record.level(), record.args());
+    for (key, value) in fields(record) {
+        write!(out, " {}={:?}", key, value).ok();
+    }
+    out
+}
+
+
+fn format_json(record: &Record) -> String {
+    let mut object = Map::new();
+    object.insert("time".to_owned(), Value::from(timestamp()));
+    object.insert("level".to_owned(), Value::from(record.level().as_str().to_lowercase()));
+    object.insert("message".to_owned(), Value::from(record.args().to_string()));
+    for (key, value) in fields(record) {
+        object.insert(key, Value::from(value));
+    }
+    Value::Object(object).to_string()
+}
+
+
+/// Syslog severity of the log level
+fn severity(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+
+/// Returns message in the RFC 3164 format with the daemon facility.
+fn format_syslog(record: &Record) -> String {
+    let mut out = format!(
+        "<{}>{}[{}]: {}",
+        3 * 8 + severity(record.level()),
+        IDENTIFIER,
+        std::process::id(),
+        record.args(),
+    );
+    for (key, value) in fields(record) {
+        write!(out, " {}={:?}", key, value).ok();
+    }
+    out
+}
+
+
+/// Appends field in the journald native protocol.
+/// Values with line breaks are serialized with explicit length.
+fn push_field(out: &mut Vec<u8>, key: &str, value: &str) {
+    out.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        out.push(b'\n');
+        out.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        out.push(b'=');
+    }
+    out.extend_from_slice(value.as_bytes());
+    out.push(b'\n');
+}
+
+
+fn format_journald(record: &Record) -> Vec<u8> {
+    let mut out = Vec::new();
+    push_field(&mut out, "MESSAGE", &record.args().to_string());
+    push_field(&mut out, "PRIORITY", &severity(record.level()).to_string());
+    push_field(&mut out, "SYSLOG_IDENTIFIER", IDENTIFIER);
+    for (key, value) in fields(record) {
+        // field names are uppercase letters, digits and underscores
+        let key: String = key.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        push_field(&mut out, &key, &value);
+    }
+    out
+}
+
+
+/// Installs global logger with the command line options.
+pub fn init(format: Option<LogFormat>, debug: bool) {
+    *LOGGER.options.write().unwrap_or_else(|err| err.into_inner()) = Options { format, debug };
+    log::set_logger(&LOGGER).ok();
+    configure(&LogConfig::default());
+}
+
+
+/// Applies logging options from the configuration.
+pub fn configure(config: &LogConfig) {
+    let options = *LOGGER.options.read().unwrap_or_else(|err| err.into_inner());
+
+    *LOGGER.format.write().unwrap_or_else(|err| err.into_inner()) = options.format.unwrap_or(config.format);
+    log::set_max_level(if options.debug || config.debug {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    });
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        std::{
+            env,
+            os::unix::net::UnixDatagram,
+            sync::{
+                OnceLock,
+                RwLock,
+            },
+        },
+
+        log::{
+            Level,
+            Record,
+        },
+        serde_json::Value,
+
+        tsplay::LogFormat,
+
+        super::{
+            Logger,
+            Options,
+            format_json,
+            format_journald,
+        },
+    };
+
+
+    #[test]
+    fn fields() {
+        let kvs = [("stream", "a"), ("input", "udp://239.0.0.1:1234")];
+        let record = Record::builder()
+            .args(format_args!("input error"))
+            .level(Level::Warn)
+            .key_values(&kvs)
+            .build();
+
+        let json: Value = serde_json::from_str(&format_json(&record)).unwrap();
+        assert_eq!(json["level"], "warn");
+        assert_eq!(json["message"], "input error");
+        assert_eq!(json["stream"], "a");
+        assert_eq!(json["input"], "udp://239.0.0.1:1234");
+
+        let journald = format_journald(&record);
+        assert_eq!(
+            journald,
+            b"MESSAGE=input error\nPRIORITY=4\nSYSLOG_IDENTIFIER=tsplay\nSTREAM=a\nINPUT=udp://239.0.0.1:1234\n",
+        );
+    }
+
+    #[test]
+    fn send_full() {
+        let path = env::temp_dir().join(format!("tsplay-log-{}.sock", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::remove_file(path).ok();
+        // receiver does not read, queue of the socket is filled
+        let _receiver = UnixDatagram::bind(path).unwrap();
+
+        let logger = Logger {
+            options: RwLock::new(Options { format: None, debug: false }),
+            format: RwLock::new(LogFormat::Syslog),
+            socket: OnceLock::new(),
+        };
+        for _ in 0 .. 10_000 {
+            assert!(logger.send(path, &[0; 1024]));
+        }
+
+        std::fs::remove_file(path).ok();
+    }
+}
@@ -1,14 +1,14 @@
-mod ts;
-mod es;
-mod misc;
-mod config;
-mod streams;
-mod application;
+mod logger;
+
 
 use {
     anyhow::Result,
 
-    application::Application,
+    tsplay::{
+        Format,
+        LogFormat,
+        Application,
+    },
 };
 
 
@@ -16,7 +16,7 @@ const DEFAULT_CONFIG_FILE: &str = "/etc/tsplay.conf";
 
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<()> {
     let args = clap::App::new(env!("CARGO_PKG_NAME"))
         .version(env!("CARGO_PKG_VERSION"))
         .author(env!("CARGO_PKG_AUTHORS"))
@@ -25,18 +25,77 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             .short("d")
             .long("debug")
             .help("enable debug"))
+        .arg(clap::Arg::with_name("log")
+            .long("log")
+            .takes_value(true)
+            .value_name("FORMAT")
+            .possible_values(&["text", "json", "syslog", "journald"])
+            .help("log format, overrides configuration"))
+        .arg(clap::Arg::with_name("format")
+            .long("format")
+            .takes_value(true)
+            .value_name("FORMAT")
+            .possible_values(&["json", "toml", "yaml"])
+            .help("configuration format, defined by the file extension by default"))
+        .arg(clap::Arg::with_name("check-config")
+            .long("check-config")
+            .help("validate configuration file and exit"))
+        .arg(clap::Arg::with_name("print-schema")
+            .long("print-schema")
+            .help("print JSON Schema of the configuration and exit"))
         .arg(clap::Arg::with_name("config")
             .takes_value(true)
             .value_name("CONFIG")
             .default_value(DEFAULT_CONFIG_FILE)
             .help("configuration file")
+        )
+        .subcommand(clap::SubCommand::with_name("probe")
+            .about("Analyze TS file and print programs, PIDs, bitrates and errors.")
+            .arg(clap::Arg::with_name("json")
+                .long("json")
+                .help("print report in JSON"))
+            .arg(clap::Arg::with_name("file")
+                .takes_value(true)
+                .value_name("FILE")
+                .required(true)
+                .help("TS file")
+            )
         ).get_matches();
 
+    // Unwrap use, because there is a validator for the log argument.
+    let format = args.value_of("log").map(|v| v.parse::<LogFormat>().unwrap());
+    logger::init(format, args.is_present("debug"));
+
+    if args.is_present("print-schema") {
+        println!("{}", serde_json::to_string_pretty(&Application::schema())?);
+        return Ok(())
+    }
+
+    if let Some(args) = args.subcommand_matches("probe") {
+        // Unwrap use, because the file argument is required.
+        let report = tsplay::probe(args.value_of("file").unwrap()).await?;
+        if args.is_present("json") {
+            println!("{}", serde_json::to_string_pretty(&report)?);
+        } else {
+            print!("{}", report);
+        }
+        return Ok(())
+    }
+
     // Unwrap use, because there is a default value and a validator for the config argument.
     let path = args.value_of("config").unwrap();
+    // Unwrap use, because there is a validator for the format argument.
+    let format = args.value_of("format").map(|v| v.parse::<Format>().unwrap());
+
+    if args.is_present("check-config") {
+        Application::new(path, format).await?;
+        println!("Configuration file \"{}\" is valid", path);
+        return Ok(())
+    }
 
-    let mut app = Application::new(&path).await?;
-    app.run().await;
+    let mut app = Application::new(&path, format).await?;
+    app.on_log_config(logger::configure);
+    app.run().await?;
 
     Ok(())
 }
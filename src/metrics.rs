@@ -0,0 +1,120 @@
+use {
+    std::{
+        fmt::Write,
+        sync::atomic::Ordering,
+    },
+
+    crate::{
+        monitor::Check,
+        stats::Stats,
+    },
+};
+
+
+/// Escapes label value for the Prometheus text format.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+
+/// Appends metric with HELP and TYPE lines and one sample per stream.
+fn metric<F>(out: &mut String, streams: &[(&str, bool, &Stats)], name: &str, kind: &str, help: &str, value: F)
+where
+    F: Fn(bool, &Stats) -> f64,
+{
+    writeln!(out, "# HELP {} {}", name, help).ok();
+    writeln!(out, "# TYPE {} {}", name, kind).ok();
+    for (stream, running, stats) in streams {
+        writeln!(out, "{}{{name=\"{}\"}} {}", name, escape(stream), value(*running, stats)).ok();
+    }
+}
+
+
+/// Returns statistics of the streams in Prometheus text exposition format.
+/// Each item of `streams` is a stream name, running state and statistics.
+pub fn render(streams: &[(&str, bool, &Stats)]) -> String {
+    let mut out = String::new();
+
+    metric(&mut out, streams, "tsplay_stream_up", "gauge",
+        "Stream is running",
+        |running, _| if running { 1.0 } else { 0.0 });
+    metric(&mut out, streams, "tsplay_input_packets_total", "counter",
+        "Packets received from the input",
+        |_, stats| stats.input_packets.load(Ordering::Relaxed) as f64);
+    metric(&mut out, streams, "tsplay_input_bytes_total", "counter",
+        "Bytes received from the input",
+        |_, stats| stats.input_bytes.load(Ordering::Relaxed) as f64);
+    metric(&mut out, streams, "tsplay_output_packets_total", "counter",
+        "Packets sent to the output",
+        |_, stats| stats.packets.load(Ordering::Relaxed) as f64);
+    metric(&mut out, streams, "tsplay_output_bytes_total", "counter",
+        "Bytes sent to the output",
+        |_, stats| stats.position.load(Ordering::Relaxed) as f64);
+    metric(&mut out, streams, "tsplay_output_errors_total", "counter",
+        "Failed writes to the output",
+        |_, stats| stats.output_errors.load(Ordering::Relaxed) as f64);
+    metric(&mut out, streams, "tsplay_output_bitrate_bps", "gauge",
+        "Output bitrate in the last second",
+        |_, stats| stats.bitrate(1) as f64);
+    metric(&mut out, streams, "tsplay_cc_errors_total", "counter",
+        "Continuity counter errors on the input",
+        |_, stats| stats.get_pids().values().map(|pid| pid.cc_errors).sum::<u64>() as f64);
+    metric(&mut out, streams, "tsplay_sync_loss_total", "counter",
+        "TS sync losses on the output",
+        |_, stats| stats.get_errors().get(&Check::SyncLoss).map(|e| e.count).unwrap_or(0) as f64);
+    metric(&mut out, streams, "tsplay_pcr_jitter_seconds", "gauge",
+        "Maximum PCR inaccuracy in the last second",
+        |_, stats| stats.pcr_jitter.load(Ordering::Relaxed) as f64 / 1e9);
+    metric(&mut out, streams, "tsplay_input_reconnects_total", "counter",
+        "Input reopens after error",
+        |_, stats| stats.input_reconnects.load(Ordering::Relaxed) as f64);
+    metric(&mut out, streams, "tsplay_loops_total", "counter",
+        "Input restarts from the beginning of file",
+        |_, stats| stats.loops.load(Ordering::Relaxed) as f64);
+
+    writeln!(out, "# HELP tsplay_tr101290_errors_total ETSI TR 101 290 errors on the output").ok();
+    writeln!(out, "# TYPE tsplay_tr101290_errors_total counter").ok();
+    for (stream, _, stats) in streams {
+        for (check, error) in stats.get_errors() {
+            writeln!(
+                out,
+                "tsplay_tr101290_errors_total{{name=\"{}\",check=\"{}\"}} {}",
+                escape(stream),
+                check,
+                error.count,
+            ).ok();
+        }
+    }
+
+    out
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        std::sync::atomic::Ordering,
+
+        crate::stats::Stats,
+
+        super::render,
+    };
+
+
+    #[test]
+    fn render_stream() {
+        let stats = Stats::default();
+        stats.input_packets.store(10, Ordering::Relaxed);
+        stats.loops.store(2, Ordering::Relaxed);
+
+        let out = render(&[("a \"b\"", true, &stats)]);
+        assert!(out.contains("# TYPE tsplay_input_packets_total counter\n"));
+        assert!(out.contains("tsplay_input_packets_total{name=\"a \\\"b\\\"\"} 10\n"));
+        assert!(out.contains("tsplay_stream_up{name=\"a \\\"b\\\"\"} 1\n"));
+        assert!(out.contains("tsplay_loops_total{name=\"a \\\"b\\\"\"} 2\n"));
+        assert!(out.contains("tsplay_input_reconnects_total{name=\"a \\\"b\\\"\"} 0\n"));
+    }
+}
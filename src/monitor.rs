@@ -0,0 +1,640 @@
+use {
+    std::{
+        fmt,
+        mem,
+        sync::Arc,
+        time::Duration,
+        collections::HashMap,
+    },
+
+    tokio::time::Instant,
+
+    crate::{
+        ts::{
+            TsPacket,
+            TS_PACKET_SIZE,
+            NULL_PID,
+            PCR_CLOCK,
+            PCR_MAX,
+            pcr_delta,
+            is_sync,
+        },
+        es::PesPacket,
+        psi::{
+            Psi,
+            Pat,
+            Pmt,
+            crc32,
+            PAT_PID,
+            CAT_PID,
+            NIT_PID,
+            SDT_PID,
+            EIT_PID,
+            TDT_PID,
+            PAT_TABLE_ID,
+            CAT_TABLE_ID,
+            PMT_TABLE_ID,
+        },
+        pipeline::{
+            CcTracker,
+            Continuity,
+        },
+        stats::Stats,
+    },
+};
+
+
+/// table_id of the time_offset_section. TOT has CRC32 without long section header
+const TOT_TABLE_ID: u8 = 0x73;
+
+/// Number of the consecutive sync bytes to acquire sync
+const SYNC_LOCK: usize = 5;
+/// Number of the consecutive corrupted sync bytes to lose sync
+const SYNC_LOST: usize = 2;
+
+/// Maximum interval between PAT sections
+const PAT_TIMEOUT: Duration = Duration::from_millis(500);
+/// Maximum interval between PMT sections
+const PMT_TIMEOUT: Duration = Duration::from_millis(500);
+/// Maximum interval between packets of the PID referenced in PMT
+const PID_TIMEOUT: Duration = Duration::from_secs(5);
+/// Maximum interval between PCR on the same PID
+const PCR_TIMEOUT: Duration = Duration::from_millis(40);
+/// Maximum interval between PTS on the same PID
+const PTS_TIMEOUT: Duration = Duration::from_millis(700);
+/// Minimal interval between CAT errors for scrambled packets without CAT
+const CAT_INTERVAL: Duration = Duration::from_secs(1);
+/// Interval of the timeout checks
+const SWEEP_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Maximum difference between consecutive PCR values in 27MHz clocks
+const PCR_MAX_DELTA: u64 = PCR_CLOCK / 10;
+/// Maximum PCR inaccuracy in 27MHz clocks (±500ns)
+const PCR_ACCURACY: u64 = 13;
+
+
+/// ETSI TR 101 290 priority 1 and priority 2 checks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Check {
+    SyncLoss,
+    SyncByte,
+    Pat,
+    Cc,
+    Pmt,
+    Pid,
+    Transport,
+    Crc,
+    PcrRepetition,
+    PcrDiscontinuity,
+    PcrAccuracy,
+    Pts,
+    Cat,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Check::SyncLoss => "1.1 TS_sync_loss",
+            Check::SyncByte => "1.2 Sync_byte_error",
+            Check::Pat => "1.3 PAT_error",
+            Check::Cc => "1.4 Continuity_count_error",
+            Check::Pmt => "1.5 PMT_error",
+            Check::Pid => "1.6 PID_error",
+            Check::Transport => "2.1 Transport_error",
+            Check::Crc => "2.2 CRC_error",
+            Check::PcrRepetition => "2.3a PCR_repetition_error",
+            Check::PcrDiscontinuity => "2.3b PCR_discontinuity_indicator_error",
+            Check::PcrAccuracy => "2.4 PCR_accuracy_error",
+            Check::Pts => "2.5 PTS_error",
+            Check::Cat => "2.6 CAT_error",
+        };
+        f.write_str(name)
+    }
+}
+
+
+/// Returns `true` if section has no CRC32 or CRC32 is valid.
+#[inline]
+fn is_crc_valid(section: &[u8]) -> bool {
+    let syntax = section.get(1).map(|b| b & 0x80 != 0).unwrap_or(false);
+    if syntax || section[0] == TOT_TABLE_ID {
+        crc32(section) == 0
+    } else {
+        true
+    }
+}
+
+
+/// Returns `true` and moves `seen` to `now` if timeout is expired.
+#[inline]
+fn is_expired(seen: &mut Instant, now: Instant, timeout: Duration) -> bool {
+    if now.saturating_duration_since(*seen) > timeout {
+        *seen = now;
+        true
+    } else {
+        false
+    }
+}
+
+
+struct PmtState {
+    psi: Psi,
+    /// Time of the last PMT section
+    seen: Instant,
+    /// Elementary stream PIDs of the program
+    pids: Vec<u16>,
+}
+
+
+struct PcrState {
+    /// Time of the last PCR
+    seen: Instant,
+    /// Last PCR value and packet position
+    last: (u64, u64),
+    /// Previous PCR value and packet position to calculate accuracy
+    prev: Option<(u64, u64)>,
+}
+
+
+/// Transport stream monitoring according to ETSI TR 101 290 priority 1 and 2.
+///
+/// Errors are counted in the stream statistics with time of the last occurrence.
+pub struct Monitor {
+    stats: Arc<Stats>,
+
+    /// Incomplete TS packet from the previous data block
+    buffer: Vec<u8>,
+    /// Time of the last data block
+    time: Option<Instant>,
+    synced: bool,
+    sync_count: usize,
+    sync_errors: usize,
+    /// Number of the packets since start
+    position: u64,
+    sweep: Option<Instant>,
+
+    cc: CcTracker,
+
+    pat: Psi,
+    pat_seen: Option<Instant>,
+    /// PMT by PMT PID
+    pmt: HashMap<u16, PmtState>,
+    /// Time of the last packet by PIDs referenced in PMT
+    pids: HashMap<u16, Instant>,
+
+    pcr: HashMap<u16, PcrState>,
+    /// Time of the last PTS by PID
+    pts: HashMap<u16, Instant>,
+
+    /// CAT and DVB SI assemblers for CRC check
+    si: HashMap<u16, Psi>,
+    cat: bool,
+    cat_error: Option<Instant>,
+}
+
+impl Monitor {
+    /// Errors are reported to `stats`.
+    pub fn new(stats: Arc<Stats>) -> Self {
+        Self {
+            stats,
+
+            buffer: Vec::new(),
+            time: None,
+            synced: false,
+            sync_count: 0,
+            sync_errors: 0,
+            position: 0,
+            sweep: None,
+
+            cc: CcTracker::default(),
+
+            pat: Psi::default(),
+            pat_seen: None,
+            pmt: HashMap::new(),
+            pids: HashMap::new(),
+
+            pcr: HashMap::new(),
+            pts: HashMap::new(),
+
+            si: HashMap::new(),
+            cat: false,
+            cat_error: None,
+        }
+    }
+
+    #[inline]
+    fn report(&self, check: Check) {
+        self.stats.report_error(check);
+    }
+
+    /// Checks timeouts of the PAT, PMT, PIDs, PCR and PTS.
+    fn sweep(&mut self, now: Instant) {
+        match self.sweep {
+            Some(last) if now.saturating_duration_since(last) < SWEEP_INTERVAL => return,
+            _ => self.sweep = Some(now),
+        }
+
+        if let Some(seen) = self.pat_seen.as_mut() {
+            if is_expired(seen, now, PAT_TIMEOUT) {
+                self.stats.report_error(Check::Pat);
+            }
+        }
+
+        for state in self.pmt.values_mut() {
+            if is_expired(&mut state.seen, now, PMT_TIMEOUT) {
+                self.stats.report_error(Check::Pmt);
+            }
+        }
+
+        for seen in self.pids.values_mut() {
+            if is_expired(seen, now, PID_TIMEOUT) {
+                self.stats.report_error(Check::Pid);
+            }
+        }
+
+        for state in self.pcr.values_mut() {
+            if is_expired(&mut state.seen, now, PCR_TIMEOUT) {
+                self.stats.report_error(Check::PcrRepetition);
+            }
+        }
+
+        for seen in self.pts.values_mut() {
+            if is_expired(seen, now, PTS_TIMEOUT) {
+                self.stats.report_error(Check::Pts);
+            }
+        }
+    }
+
+    fn on_pat(&mut self, section: &[u8], now: Instant) {
+        if section[0] != PAT_TABLE_ID {
+            self.report(Check::Pat);
+            return
+        }
+
+        if ! is_crc_valid(section) {
+            self.report(Check::Crc);
+            return
+        }
+
+        self.pat_seen = Some(now);
+
+        let pat = match Pat::parse(section) {
+            Some(v) => v,
+            None => return,
+        };
+
+        let mut pmt = HashMap::new();
+        for item in pat.items.iter().filter(|item| item.pnr != 0) {
+            let state = self.pmt.remove(&item.pid).unwrap_or_else(|| PmtState {
+                psi: Psi::default(),
+                seen: now,
+                pids: Vec::new(),
+            });
+            pmt.insert(item.pid, state);
+        }
+        self.pmt = pmt;
+
+        self.update_pids(now);
+    }
+
+    fn on_pmt(&mut self, pid: u16, section: &[u8], now: Instant) {
+        if ! is_crc_valid(section) {
+            self.report(Check::Crc);
+            return
+        }
+
+        let pmt = match Pmt::parse(section) {
+            Some(v) if section[0] == PMT_TABLE_ID => v,
+            _ => return,
+        };
+
+        if let Some(state) = self.pmt.get_mut(&pid) {
+            state.seen = now;
+            state.pids = pmt.items.iter().map(|item| item.pid).collect();
+        }
+
+        self.update_pids(now);
+    }
+
+    /// Keeps PIDs referenced in the PMT of the current programs.
+    fn update_pids(&mut self, now: Instant) {
+        let mut pids = HashMap::new();
+        for pid in self.pmt.values().flat_map(|state| state.pids.iter()) {
+            let seen = self.pids.get(pid).copied().unwrap_or(now);
+            pids.insert(*pid, seen);
+        }
+        self.pids = pids;
+    }
+
+    fn on_si(&mut self, pid: u16, section: &[u8]) {
+        if pid == CAT_PID {
+            if section[0] != CAT_TABLE_ID {
+                self.report(Check::Cat);
+                return
+            }
+            self.cat = true;
+        }
+
+        if ! is_crc_valid(section) {
+            self.report(Check::Crc);
+        }
+    }
+
+    fn on_pcr(&mut self, pid: u16, pcr: u64, discontinuity: bool, now: Instant) {
+        let position = self.position;
+
+        let state = match self.pcr.get_mut(&pid) {
+            Some(v) => v,
+            None => {
+                self.pcr.insert(pid, PcrState { seen: now, last: (pcr, position), prev: None });
+                return
+            }
+        };
+
+        state.seen = now;
+
+        let (last_pcr, last_position) = state.last;
+        let prev = state.prev;
+        state.last = (pcr, position);
+        state.prev = Some((last_pcr, last_position));
+
+        if discontinuity {
+            state.prev = None;
+            return
+        }
+
+        // negative difference is wrapped to the large value
+        if pcr_delta(last_pcr, pcr) > PCR_MAX_DELTA {
+            state.prev = None;
+            self.report(Check::PcrDiscontinuity);
+            return
+        }
+
+        // expected PCR by the transport rate between previous PCR values
+        if let Some((prev_pcr, prev_position)) = prev {
+            let delta = u128::from(pcr_delta(prev_pcr, last_pcr));
+            let expected = last_pcr + (delta * u128::from(position - last_position)
+                / u128::from(last_position - prev_position)) as u64;
+            let diff = pcr_delta(expected % PCR_MAX, pcr);
+            let diff = diff.min(PCR_MAX - diff);
+            self.stats.update_pcr_jitter(diff * 1000 / 27);
+            if diff > PCR_ACCURACY {
+                self.report(Check::PcrAccuracy);
+            }
+        }
+    }
+
+    fn check(&mut self, ts: &TsPacket, now: Instant) {
+        self.position += 1;
+        if self.pat_seen.is_none() {
+            self.pat_seen = Some(now);
+        }
+        self.sweep(now);
+
+        if ts.is_error() {
+            self.report(Check::Transport);
+            return
+        }
+
+        if self.cc.check(ts) == Continuity::Error {
+            self.report(Check::Cc);
+        }
+
+        let pid = ts.get_pid();
+        if pid == NULL_PID {
+            return
+        }
+
+        if let Some(seen) = self.pids.get_mut(&pid) {
+            *seen = now;
+        }
+
+        if let Some(pcr) = ts.get_pcr() {
+            self.on_pcr(pid, pcr, ts.is_discontinuity(), now);
+        }
+
+        if ts.is_scrambled() {
+            if pid == PAT_PID {
+                self.report(Check::Pat);
+            } else if self.pmt.contains_key(&pid) {
+                self.report(Check::Pmt);
+            } else if ! self.cat {
+                match self.cat_error {
+                    Some(last) if now.saturating_duration_since(last) < CAT_INTERVAL => {}
+                    _ => {
+                        self.cat_error = Some(now);
+                        self.report(Check::Cat);
+                    }
+                }
+            }
+            return
+        }
+
+        if ts.is_pusi() && ts.is_payload() && ts.is_pes() &&
+            PesPacket::new(ts.get_payload()).ok().and_then(|pes| pes.get_pts()).is_some()
+        {
+            self.pts.insert(pid, now);
+        }
+
+        match pid {
+            PAT_PID => {
+                for section in self.pat.push(ts) {
+                    self.on_pat(&section, now);
+                }
+            }
+            CAT_PID | NIT_PID | SDT_PID | EIT_PID | TDT_PID => {
+                let sections = self.si.entry(pid).or_default().push(ts);
+                for section in sections {
+                    self.on_si(pid, &section);
+                }
+            }
+            _ => {
+                let sections = match self.pmt.get_mut(&pid) {
+                    Some(state) => state.psi.push(ts),
+                    None => return,
+                };
+                for section in sections {
+                    self.on_pmt(pid, &section, now);
+                }
+            }
+        }
+    }
+
+    /// Checks block of the transport stream sent at the given time.
+    /// Time of each packet is interpolated between previous and current block.
+    pub fn push(&mut self, data: &[u8], time: Instant) {
+        let last = self.time.unwrap_or(time);
+        let span = time.saturating_duration_since(last);
+        self.time = Some(time);
+
+        let mut buffer = mem::take(&mut self.buffer);
+        buffer.extend_from_slice(data);
+        let total = buffer.len() as f64;
+
+        let mut skip = 0;
+        while buffer.len() - skip >= TS_PACKET_SIZE {
+            let packet = &buffer[skip .. skip + TS_PACKET_SIZE];
+            let now = last + span.mul_f64((skip + TS_PACKET_SIZE) as f64 / total);
+
+            if is_sync(packet) {
+                self.sync_errors = 0;
+                if ! self.synced {
+                    self.sync_count += 1;
+                    self.synced = self.sync_count >= SYNC_LOCK;
+                }
+                if self.synced {
+                    if let Ok(ts) = TsPacket::new(packet) {
+                        self.check(&ts, now);
+                    }
+                }
+                skip += TS_PACKET_SIZE;
+            } else if self.synced {
+                self.report(Check::SyncByte);
+                self.sync_errors += 1;
+                if self.sync_errors >= SYNC_LOST {
+                    self.report(Check::SyncLoss);
+                    self.synced = false;
+                    self.sync_count = 0;
+                }
+                skip += TS_PACKET_SIZE;
+            } else {
+                self.sync_count = 0;
+                skip += 1;
+            }
+        }
+
+        buffer.drain(.. skip);
+        self.buffer = buffer;
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        std::{
+            sync::Arc,
+            time::Duration,
+        },
+
+        tokio::time::Instant,
+
+        crate::{
+            ts::{
+                TS_PACKET_SIZE,
+                PCR_CLOCK,
+                TsPacketMut,
+            },
+            stats::Stats,
+        },
+
+        super::{
+            Monitor,
+            Check,
+        },
+    };
+
+
+    fn count(stats: &Stats, check: Check) -> u64 {
+        stats.get_errors().get(&check).map(|e| e.count).unwrap_or(0)
+    }
+
+    fn pcr_packet(cc: u8, pcr: u64) -> Vec<u8> {
+        let mut ts = vec![0x47, 0x01, 0x00, 0x20 | cc, 183, 0x10];
+        ts.resize(TS_PACKET_SIZE, 0xFF);
+        TsPacketMut::new(&mut ts).unwrap().set_pcr(Some(pcr));
+        ts
+    }
+
+    #[test]
+    fn sync() {
+        let stats = Arc::new(Stats::default());
+        let mut monitor = Monitor::new(stats.clone());
+
+        let mut data = Vec::new();
+        for _ in 0 .. 10 {
+            TsPacketMut::push_null(&mut data);
+        }
+        // two corrupted sync bytes
+        data[6 * TS_PACKET_SIZE] = 0x00;
+        data[7 * TS_PACKET_SIZE] = 0x00;
+
+        monitor.push(&data, Instant::now());
+
+        assert_eq!(count(&stats, Check::SyncByte), 2);
+        assert_eq!(count(&stats, Check::SyncLoss), 1);
+    }
+
+    #[test]
+    fn pat_timeout() {
+        let stats = Arc::new(Stats::default());
+        let mut monitor = Monitor::new(stats.clone());
+
+        let start = Instant::now();
+        let mut data = Vec::new();
+        for _ in 0 .. 10 {
+            TsPacketMut::push_null(&mut data);
+        }
+        monitor.push(&data, start);
+        assert_eq!(count(&stats, Check::Pat), 0);
+
+        monitor.push(&data, start + Duration::from_millis(600));
+        assert_eq!(count(&stats, Check::Pat), 1);
+    }
+
+    #[test]
+    fn pcr() {
+        let stats = Arc::new(Stats::default());
+        let mut monitor = Monitor::new(stats.clone());
+
+        let start = Instant::now();
+        let mut data = Vec::new();
+        for _ in 0 .. 5 {
+            TsPacketMut::push_null(&mut data);
+        }
+        // PCR every 10 packets, 1 packet per 1000 clocks
+        for i in 0 .. 4 {
+            let error = if i == 3 { 100 } else { 0 };
+            data.extend(pcr_packet(0, 1_000_000 + i * 10_000 + error));
+            for _ in 0 .. 9 {
+                TsPacketMut::push_null(&mut data);
+            }
+        }
+        // discontinuity without indicator
+        data.extend(pcr_packet(0, 1_000_000 + 40_000 + PCR_CLOCK));
+
+        monitor.push(&data, start);
+
+        assert_eq!(count(&stats, Check::PcrAccuracy), 1);
+        assert_eq!(count(&stats, Check::PcrDiscontinuity), 1);
+        assert_eq!(count(&stats, Check::Cc), 0);
+    }
+
+
+    #[test]
+    fn pcr_repetition() {
+        let stats = Arc::new(Stats::default());
+        let mut monitor = Monitor::new(stats.clone());
+
+        let start = Instant::now();
+        let mut nulls = Vec::new();
+        for _ in 0 .. 5 {
+            TsPacketMut::push_null(&mut nulls);
+        }
+        let ms = PCR_CLOCK / 1000;
+
+        let mut data = nulls.clone();
+        data.extend(pcr_packet(0, 1_000_000));
+        monitor.push(&data, start);
+
+        // PCR after 30ms is in time
+        monitor.push(&pcr_packet(1, 1_000_000 + 30 * ms), start + Duration::from_millis(30));
+        assert_eq!(count(&stats, Check::PcrRepetition), 0);
+
+        // no PCR for 60ms
+        monitor.push(&nulls, start + Duration::from_millis(90));
+        monitor.push(&pcr_packet(2, 1_000_000 + 95 * ms), start + Duration::from_millis(95));
+        assert_eq!(count(&stats, Check::PcrRepetition), 1);
+        assert_eq!(count(&stats, Check::PcrDiscontinuity), 0);
+    }
+}
@@ -0,0 +1,308 @@
+use {
+    std::{
+        collections::{
+            BTreeMap,
+            HashMap,
+            HashSet,
+        },
+        iter,
+    },
+
+    crate::{
+        ts::{
+            TsPacket,
+            TsPacketMut,
+            NULL_PID,
+        },
+        psi::{
+            Psi,
+            Pat,
+            PatItem,
+            Pmt,
+            Sdt,
+            SdtItem,
+            descriptors,
+            get_ca_pid,
+            map_ca_pids,
+            CA_DESCRIPTOR,
+            PAT_PID,
+            SDT_PID,
+            SDT_ACTUAL_TABLE_ID,
+        },
+    },
+};
+
+
+/// First PID available for the elementary streams and PMT.
+/// Lower PIDs are reserved for the PSI/SI tables.
+const FIRST_PID: u16 = 0x0020;
+
+
+/// PIDs and program numbers used on the multiplexer output.
+#[derive(Default)]
+pub struct Allocator {
+    pids: HashSet<u16>,
+    pnrs: HashSet<u16>,
+}
+
+impl Allocator {
+    /// Returns `pid` if it is free, otherwise the first free PID.
+    fn pid(&mut self, pid: u16) -> u16 {
+        if (FIRST_PID .. NULL_PID).contains(&pid) && self.pids.insert(pid) {
+            return pid
+        }
+
+        match (FIRST_PID .. NULL_PID).find(|pid| ! self.pids.contains(pid)) {
+            Some(pid) => {
+                self.pids.insert(pid);
+                pid
+            }
+            None => NULL_PID,
+        }
+    }
+
+    /// Returns `pnr` if it is free, otherwise the first free program number.
+    fn pnr(&mut self, pnr: u16) -> u16 {
+        if pnr != 0 && self.pnrs.insert(pnr) {
+            return pnr
+        }
+
+        match (1 ..= u16::MAX).find(|pnr| ! self.pnrs.contains(pnr)) {
+            Some(pnr) => {
+                self.pnrs.insert(pnr);
+                pnr
+            }
+            None => 0,
+        }
+    }
+}
+
+
+/// State of the single multiplexer input.
+#[derive(Default)]
+pub struct Input {
+    /// Incomplete TS packet from the previous data block
+    pub buffer: Vec<u8>,
+    /// Sync byte at the start of the buffer is confirmed
+    pub synced: bool,
+
+    /// Input PID to output PID
+    pids: HashMap<u16, u16>,
+    /// Input program_number to output program_number
+    pnrs: HashMap<u16, u16>,
+    /// PMT, elementary stream, PCR and ECM PIDs of the input by input program_number
+    program_pids: HashMap<u16, HashSet<u16>>,
+
+    pat: Psi,
+    /// PMT assemblers by input PMT PID
+    pmt: HashMap<u16, Psi>,
+    sdt: Psi,
+
+    /// Programs with output PMT PID and program number
+    pub programs: Vec<PatItem>,
+    /// Remapped PMT sections by output PMT PID
+    pub pmts: BTreeMap<u16, Vec<u8>>,
+    /// Services with output service_id
+    pub services: Vec<SdtItem>,
+    /// Programs, PMT or services are changed since the tables of the multiplexer are built
+    pub changed: bool,
+}
+
+impl Input {
+    fn map_pid(&mut self, pid: u16, alloc: &mut Allocator) -> u16 {
+        *self.pids.entry(pid).or_insert_with(|| alloc.pid(pid))
+    }
+
+    fn map_pnr(&mut self, pnr: u16, alloc: &mut Allocator) -> u16 {
+        *self.pnrs.entry(pnr).or_insert_with(|| alloc.pnr(pnr))
+    }
+
+    /// Sets input PIDs of the program. PIDs not used anymore are returned to allocator.
+    fn set_program_pids(&mut self, pnr: u16, pids: HashSet<u16>, alloc: &mut Allocator) {
+        let old = self.program_pids.insert(pnr, pids).unwrap_or_default();
+        for pid in old {
+            if self.program_pids.values().any(|pids| pids.contains(&pid)) {
+                continue
+            }
+            if let Some(to) = self.pids.remove(&pid) {
+                alloc.pids.remove(&to);
+            }
+        }
+    }
+
+    /// Removes program with all its PIDs and returns them to allocator.
+    fn remove_program(&mut self, pnr: u16, alloc: &mut Allocator) {
+        self.set_program_pids(pnr, HashSet::new(), alloc);
+        self.program_pids.remove(&pnr);
+
+        if let Some(to) = self.pnrs.remove(&pnr) {
+            alloc.pnrs.remove(&to);
+        }
+    }
+
+    /// Returns PIDs and program numbers of this input back to allocator.
+    pub fn release(&self, alloc: &mut Allocator) {
+        for pid in self.pids.values() {
+            alloc.pids.remove(pid);
+        }
+
+        for pnr in self.pnrs.values() {
+            alloc.pnrs.remove(pnr);
+        }
+    }
+
+    fn on_pat(&mut self, section: &[u8], alloc: &mut Allocator) {
+        let pat = match Pat::parse(section) {
+            Some(v) => v,
+            None => return,
+        };
+
+        let removed: Vec<u16> = self.program_pids.keys()
+            .filter(|&&pnr| ! pat.items.iter().any(|item| item.pnr == pnr))
+            .copied()
+            .collect();
+        for pnr in removed {
+            self.remove_program(pnr, alloc);
+        }
+
+        let mut pmt = HashMap::new();
+        let mut programs = Vec::new();
+
+        for item in pat.items.iter().filter(|item| item.pnr != 0) {
+            let state = self.pmt.remove(&item.pid).unwrap_or_default();
+            pmt.insert(item.pid, state);
+
+            if self.program_pids.get(&item.pnr).map_or(true, |pids| ! pids.contains(&item.pid)) {
+                // new program or PMT PID is changed
+                self.set_program_pids(item.pnr, iter::once(item.pid).collect(), alloc);
+            }
+
+            programs.push(PatItem {
+                pnr: self.map_pnr(item.pnr, alloc),
+                pid: self.map_pid(item.pid, alloc),
+            });
+        }
+
+        self.pmt = pmt;
+        self.pmts.retain(|pid, _| programs.iter().any(|item| item.pid == *pid));
+
+        let services = self.services.len();
+        self.services.retain(|service| programs.iter().any(|item| item.pnr == service.sid));
+        if self.services.len() != services {
+            self.changed = true;
+        }
+
+        if self.programs != programs {
+            self.programs = programs;
+            self.changed = true;
+        }
+    }
+
+    fn on_pmt(&mut self, pid: u16, section: &[u8], alloc: &mut Allocator) {
+        let mut pmt = match Pmt::parse(section) {
+            Some(v) => v,
+            None => return,
+        };
+
+        let pnr = pmt.header.table_id_extension;
+        if ! self.program_pids.get(&pnr).is_some_and(|pids| pids.contains(&pid)) {
+            // program is not in the PAT
+            return
+        }
+
+        let mut pids: HashSet<u16> = pmt.items.iter().map(|item| item.pid).collect();
+        pids.insert(pid);
+        if pmt.pcr_pid != NULL_PID {
+            pids.insert(pmt.pcr_pid);
+        }
+        let ecm = descriptors(&pmt.descriptors)
+            .chain(pmt.items.iter().flat_map(|item| descriptors(&item.descriptors)))
+            .filter(|(tag, _)| *tag == CA_DESCRIPTOR)
+            .filter_map(|(_, data)| get_ca_pid(data));
+        pids.extend(ecm);
+        self.set_program_pids(pnr, pids, alloc);
+
+        pmt.header.table_id_extension = self.map_pnr(pnr, alloc);
+
+        if pmt.pcr_pid != NULL_PID {
+            pmt.pcr_pid = self.map_pid(pmt.pcr_pid, alloc);
+        }
+
+        pmt.descriptors = map_ca_pids(&pmt.descriptors, |pid| Some(self.map_pid(pid, alloc)));
+        for item in pmt.items.iter_mut() {
+            item.pid = self.map_pid(item.pid, alloc);
+            item.descriptors = map_ca_pids(&item.descriptors, |pid| Some(self.map_pid(pid, alloc)));
+        }
+
+        let to = self.map_pid(pid, alloc);
+        let section = pmt.build();
+        if self.pmts.get(&to) != Some(&section) {
+            self.pmts.insert(to, section);
+            self.changed = true;
+        }
+    }
+
+    fn on_sdt(&mut self, section: &[u8], alloc: &mut Allocator) {
+        let sdt = match Sdt::parse(section) {
+            Some(v) => v,
+            None => return,
+        };
+
+        if sdt.header.table_id != SDT_ACTUAL_TABLE_ID {
+            return
+        }
+
+        // services without program in the PAT are skipped
+        let mut services = Vec::new();
+        for mut item in sdt.items {
+            if self.program_pids.contains_key(&item.sid) {
+                item.sid = self.map_pnr(item.sid, alloc);
+                services.push(item);
+            }
+        }
+
+        if self.services != services {
+            self.services = services;
+            self.changed = true;
+        }
+    }
+
+    /// Handles input TS packet. Elementary streams are appended to the `out`.
+    /// PAT, PMT and SDT are collected to build tables of the multiplexer.
+    /// Other SI tables, null packets and PIDs not described in the PMT are dropped.
+    pub fn process(&mut self, ts: &TsPacket, alloc: &mut Allocator, out: &mut Vec<u8>) {
+        let pid = ts.get_pid();
+
+        if pid == PAT_PID {
+            for section in self.pat.push(ts) {
+                self.on_pat(&section, alloc);
+            }
+            return
+        }
+
+        if pid == SDT_PID {
+            for section in self.sdt.push(ts) {
+                self.on_sdt(&section, alloc);
+            }
+            return
+        }
+
+        if pid < FIRST_PID || pid == NULL_PID {
+            return
+        }
+
+        if let Some(psi) = self.pmt.get_mut(&pid) {
+            for section in psi.push(ts) {
+                self.on_pmt(pid, &section, alloc);
+            }
+            return
+        }
+
+        // PIDs of the programs are mapped by PAT and PMT, other PIDs are dropped
+        let to = match self.pids.get(&pid) {
+            Some(&to) => to,
+            None => return,
+        };
+        TsPacketMut::push_packet(out, ts).set_pid(to);
+    }
+}
@@ -0,0 +1,676 @@
+use {
+    std::{
+        mem,
+        collections::BTreeMap,
+        time::Duration,
+    },
+
+    crate::{
+        ts::{
+            TsIter,
+            TsPacketMut,
+            TS_PACKET_SIZE,
+            PcrRestamp,
+        },
+        psi::{
+            Carousel,
+            Nit,
+            NitItem,
+            Pat,
+            PatItem,
+            Sdt,
+            SdtItem,
+            SectionHeader,
+            descriptors,
+            encode_text,
+            PSI_MAX_SIZE,
+            PAT_PID,
+            PAT_TABLE_ID,
+            NIT_PID,
+            NIT_ACTUAL_TABLE_ID,
+            SDT_PID,
+            SDT_ACTUAL_TABLE_ID,
+            NETWORK_NAME_DESCRIPTOR,
+            SERVICE_LIST_DESCRIPTOR,
+            SERVICE_DESCRIPTOR,
+        },
+    },
+};
+
+mod input;
+use input::{
+    Input,
+    Allocator,
+};
+
+
+/// Maximum delay of the queued packets in milliseconds. Packets above are dropped
+const MAX_DELAY_MS: u64 = 1000;
+
+
+/// Returns number of packets transmitted in `ms` milliseconds at given `bitrate`.
+#[inline]
+fn packets_in(bitrate: u64, ms: u64) -> usize {
+    let bits = (TS_PACKET_SIZE * 8) as u64;
+    ((bitrate * ms / 1000 / bits) as usize).max(1)
+}
+
+
+/// Repetition intervals of the PSI/SI tables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Intervals {
+    pub pat: Duration,
+    pub pmt: Duration,
+    pub sdt: Duration,
+    pub nit: Duration,
+}
+
+impl Default for Intervals {
+    fn default() -> Self {
+        Self {
+            pat: Duration::from_millis(100),
+            pmt: Duration::from_millis(100),
+            sdt: Duration::from_millis(1000),
+            nit: Duration::from_millis(10000),
+        }
+    }
+}
+
+
+/// Splits items into the sections. `header` is a size of the section without items
+/// including CRC32, `size` returns size of the item in the section.
+fn split<T: Clone, F: Fn(&T) -> usize>(items: &[T], header: usize, size: F) -> Vec<Vec<T>> {
+    let mut sections: Vec<Vec<T>> = vec![Vec::new()];
+    let mut section_size = header;
+
+    for item in items {
+        let item_size = size(item);
+        if section_size + item_size > PSI_MAX_SIZE && ! sections[sections.len() - 1].is_empty() {
+            sections.push(Vec::new());
+            section_size = header;
+        }
+        section_size += item_size;
+        sections.last_mut().unwrap().push(item.clone());
+    }
+
+    sections
+}
+
+
+/// Version of the generated table. Incremented when table content is changed.
+#[derive(Default)]
+struct Version<T> {
+    content: T,
+    version: u8,
+}
+
+impl<T: PartialEq> Version<T> {
+    fn update(&mut self, content: T) -> u8 {
+        if content != self.content {
+            self.content = content;
+            self.version = (self.version + 1) & 0x1F;
+        }
+        self.version
+    }
+}
+
+
+/// Network of the multiplexer output described in the NIT.
+struct Network {
+    network_id: u16,
+    name: String,
+}
+
+
+/// Multi-program transport stream multiplexer.
+///
+/// Combines programs of the several inputs into one constant bitrate output.
+/// Colliding PIDs and program numbers are remapped. PAT, SDT and optional NIT are built
+/// from the programs and services of all inputs and repeated with PMT of the inputs
+/// at the regular intervals. Gaps in the output are filled with null packets.
+pub struct Multiplexer {
+    tsid: u16,
+    onid: u16,
+    bitrate: u64,
+
+    inputs: BTreeMap<usize, Input>,
+    alloc: Allocator,
+    /// Elementary streams of all inputs
+    queue: Vec<u8>,
+    /// Maximum size of the queue in bytes
+    queue_limit: usize,
+    /// PSI packets to send before the queue
+    psi: Vec<u8>,
+
+    /// Number of the packets sent to the output
+    position: usize,
+    restamp: PcrRestamp,
+
+    carousel: Carousel,
+    intervals: Intervals,
+    network: Option<Network>,
+
+    pat: Version<Vec<PatItem>>,
+    sdt: Version<Vec<SdtItem>>,
+    nit: Version<Vec<u8>>,
+    /// Tables should be rebuilt before the next output
+    changed: bool,
+}
+
+impl Multiplexer {
+    /// `tsid` and `onid` are identifiers of the output stream, `bitrate` is an output bitrate
+    /// in bits per second.
+    pub fn new(tsid: u16, onid: u16, bitrate: u64) -> Self {
+        Self {
+            tsid,
+            onid,
+            bitrate,
+
+            inputs: BTreeMap::new(),
+            alloc: Allocator::default(),
+            queue: Vec::new(),
+            queue_limit: packets_in(bitrate, MAX_DELAY_MS) * TS_PACKET_SIZE,
+            psi: Vec::new(),
+
+            position: 0,
+            restamp: PcrRestamp::new(bitrate),
+
+            carousel: Carousel::default(),
+            intervals: Intervals::default(),
+            network: None,
+
+            pat: Version::default(),
+            sdt: Version::default(),
+            nit: Version::default(),
+            changed: true,
+        }
+    }
+
+    /// Sets repetition intervals of the PSI/SI tables.
+    pub fn set_intervals(&mut self, intervals: Intervals) {
+        self.intervals = intervals;
+        self.changed = true;
+    }
+
+    /// Enables NIT with given `network_id` and network name.
+    pub fn set_network(&mut self, network_id: u16, name: &str) {
+        self.network = Some(Network {
+            network_id,
+            name: name.to_owned(),
+        });
+        self.changed = true;
+    }
+
+    /// Appends data from the input with given identifier.
+    pub fn push(&mut self, id: usize, data: &[u8]) {
+        let input = self.inputs.entry(id).or_default();
+
+        let mut buffer = mem::take(&mut input.buffer);
+        buffer.extend_from_slice(data);
+
+        let mut packets = TsIter::resume(&buffer, input.synced);
+        for ts in &mut packets {
+            input.process(&ts, &mut self.alloc, &mut self.queue);
+        }
+
+        input.synced = packets.is_synced();
+        if mem::take(&mut input.changed) {
+            self.changed = true;
+        }
+        let skip = buffer.len() - packets.remainder().len();
+        buffer.drain(.. skip);
+        input.buffer = buffer;
+    }
+
+    /// Removes input with all its programs.
+    pub fn close(&mut self, id: usize) {
+        if let Some(input) = self.inputs.remove(&id) {
+            input.release(&mut self.alloc);
+            self.changed = true;
+        }
+    }
+
+    /// Returns time of the current output position.
+    #[inline]
+    fn time(&self) -> Duration {
+        let bits = (self.position * TS_PACKET_SIZE * 8) as u128;
+        Duration::from_nanos((bits * 1_000_000_000 / u128::from(self.bitrate)) as u64)
+    }
+
+    fn update_pat(&mut self) {
+        let items: Vec<PatItem> = self.inputs.values()
+            .flat_map(|input| input.programs.iter().cloned())
+            .collect();
+        let version = self.pat.update(items);
+
+        let mut items = self.pat.content.clone();
+        if self.network.is_some() {
+            items.insert(0, PatItem { pnr: 0, pid: NIT_PID });
+        }
+
+        // 8 bytes of the header, 4 bytes of CRC32 and 4 bytes of the program
+        let sections = split(&items, 8 + 4, |_| 4);
+        let last_section_number = (sections.len() - 1) as u8;
+        let sections = sections.into_iter().enumerate().map(|(section_number, items)| {
+            Pat {
+                header: SectionHeader {
+                    table_id: PAT_TABLE_ID,
+                    table_id_extension: self.tsid,
+                    version,
+                    section_number: section_number as u8,
+                    last_section_number,
+                },
+                items,
+            }.build()
+        }).collect();
+
+        self.carousel.set(PAT_PID, sections, self.intervals.pat);
+    }
+
+    fn update_sdt(&mut self) {
+        let items: Vec<SdtItem> = self.inputs.values()
+            .flat_map(|input| input.services.iter().cloned())
+            .collect();
+        let version = self.sdt.update(items);
+
+        if self.sdt.content.is_empty() {
+            return
+        }
+
+        // 11 bytes of the header, 4 bytes of CRC32 and 5 bytes of the service header
+        let sections = split(&self.sdt.content, 11 + 4, |item| 5 + item.descriptors.len());
+        let last_section_number = (sections.len() - 1) as u8;
+        let sections = sections.into_iter().enumerate().map(|(section_number, items)| {
+            Sdt {
+                header: SectionHeader {
+                    table_id: SDT_ACTUAL_TABLE_ID,
+                    table_id_extension: self.tsid,
+                    version,
+                    section_number: section_number as u8,
+                    last_section_number,
+                },
+                onid: self.onid,
+                items,
+            }.build()
+        }).collect();
+
+        self.carousel.set(SDT_PID, sections, self.intervals.sdt);
+    }
+
+    fn update_nit(&mut self) {
+        let network = match &self.network {
+            Some(v) => v,
+            None => return,
+        };
+
+        // service_list_descriptor with service_type from the service_descriptor.
+        // Programs without service in the SDT are listed as digital television service
+        let mut services = Vec::new();
+        for item in &self.pat.content {
+            let service_type = self.sdt.content.iter()
+                .find(|service| service.sid == item.pnr)
+                .and_then(|service| {
+                    descriptors(&service.descriptors).find(|(tag, _)| *tag == SERVICE_DESCRIPTOR)
+                })
+                .and_then(|(_, data)| data.first().copied())
+                .unwrap_or(0x01);
+            services.extend_from_slice(&item.pnr.to_be_bytes());
+            services.push(service_type);
+        }
+        let version = self.nit.update(services);
+
+        let mut descriptors = Vec::new();
+        let name = encode_text(&network.name);
+        descriptors.push(NETWORK_NAME_DESCRIPTOR);
+        descriptors.push(name.len().min(255) as u8);
+        descriptors.extend_from_slice(&name[.. name.len().min(255)]);
+
+        let service_lists: Vec<&[u8]> = self.nit.content.chunks(255 / 3 * 3).collect();
+
+        // network descriptors are repeated in each section. 10 bytes of the header,
+        // 2 bytes of the transport_stream_loop_length, 6 bytes of the transport stream header
+        // and 4 bytes of CRC32
+        let sections = split(&service_lists, 10 + descriptors.len() + 2 + 6 + 4, |chunk| 2 + chunk.len());
+        let last_section_number = (sections.len() - 1) as u8;
+        let sections = sections.into_iter().enumerate().map(|(section_number, service_lists)| {
+            let mut item = NitItem {
+                tsid: self.tsid,
+                onid: self.onid,
+                descriptors: Vec::new(),
+            };
+            for chunk in service_lists {
+                item.descriptors.push(SERVICE_LIST_DESCRIPTOR);
+                item.descriptors.push(chunk.len() as u8);
+                item.descriptors.extend_from_slice(chunk);
+            }
+
+            Nit {
+                header: SectionHeader {
+                    table_id: NIT_ACTUAL_TABLE_ID,
+                    table_id_extension: network.network_id,
+                    version,
+                    section_number: section_number as u8,
+                    last_section_number,
+                },
+                descriptors: descriptors.clone(),
+                items: vec![item],
+            }.build()
+        }).collect();
+
+        self.carousel.set(NIT_PID, sections, self.intervals.nit);
+    }
+
+    /// Rebuilds tables of the multiplexer from the current state of the inputs.
+    /// Called only if programs, services or PMT of the inputs are changed.
+    fn update_tables(&mut self) {
+        self.update_pat();
+        self.update_sdt();
+        self.update_nit();
+
+        for input in self.inputs.values() {
+            for (&pid, section) in &input.pmts {
+                self.carousel.set(pid, vec![section.clone()], self.intervals.pmt);
+            }
+        }
+
+        let inputs = &self.inputs;
+        let sdt = ! self.sdt.content.is_empty();
+        let nit = self.network.is_some();
+        self.carousel.retain(|pid| match pid {
+            PAT_PID => true,
+            SDT_PID => sdt,
+            NIT_PID => nit,
+            _ => inputs.values().any(|input| input.pmts.contains_key(&pid)),
+        });
+    }
+
+    /// Appends `count` packets to the `out`. PSI tables are inserted at the regular intervals,
+    /// gaps are filled with null packets. PCR is restamped according to the packet position.
+    /// Returns number of packets dropped because the inputs exceed output bitrate.
+    pub fn pull(&mut self, count: usize, out: &mut Vec<u8>) -> usize {
+        let mut psi_skip = 0;
+        let mut queue_skip = 0;
+
+        if mem::take(&mut self.changed) {
+            self.update_tables();
+        }
+
+        for _ in 0 .. count {
+            let start = out.len();
+
+            let time = self.time();
+            self.carousel.push(time, &mut self.psi);
+
+            if psi_skip < self.psi.len() {
+                out.extend_from_slice(&self.psi[psi_skip .. psi_skip + TS_PACKET_SIZE]);
+                psi_skip += TS_PACKET_SIZE;
+            } else if queue_skip < self.queue.len() {
+                out.extend_from_slice(&self.queue[queue_skip .. queue_skip + TS_PACKET_SIZE]);
+                queue_skip += TS_PACKET_SIZE;
+            } else {
+                TsPacketMut::push_null(out);
+            }
+
+            self.restamp.restamp(&mut out[start ..]);
+            self.position += 1;
+        }
+
+        self.psi.drain(.. psi_skip);
+        self.queue.drain(.. queue_skip);
+
+        if self.queue.len() > self.queue_limit {
+            let dropped = self.queue.len() - self.queue_limit;
+            self.queue.drain(.. dropped);
+            dropped / TS_PACKET_SIZE
+        } else {
+            0
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        std::time::Duration,
+
+        crate::{
+            ts::{
+                TsPacket,
+                TsIter,
+                TS_PACKET_SIZE,
+                NULL_PID,
+            },
+            psi::{
+                Psi,
+                Pat,
+                PatItem,
+                Nit,
+                Pmt,
+                SectionHeader,
+                packetize,
+                descriptors,
+                get_ca_pid,
+                PAT_PID,
+                NIT_PID,
+                SDT_PID,
+            },
+        },
+
+        super::{
+            Multiplexer,
+            Intervals,
+        },
+    };
+
+
+    fn input() -> Vec<u8> {
+        let pat = Pat {
+            header: SectionHeader { table_id: 0x00, table_id_extension: 1, .. Default::default() },
+            items: vec![ PatItem { pnr: 1, pid: 0x1000 } ],
+        };
+
+        // PMT of program 1: video on PID 0x100
+        let pmt = SectionHeader { table_id: 0x02, table_id_extension: 1, .. Default::default() }
+            .build(&[0xE1, 0x00, 0xF0, 0x00, 0x1B, 0xE1, 0x00, 0xF0, 0x00]);
+
+        let mut out = Vec::new();
+        packetize(&pat.build(), 0, &mut 0, &mut out);
+        packetize(&pmt, 0x1000, &mut 0, &mut out);
+        packetize(&[0x00; 4], 0x100, &mut 0, &mut out);
+        out
+    }
+
+    #[test]
+    fn collision() {
+        let mut mux = Multiplexer::new(1, 1, 1_000_000);
+        mux.push(0, &input());
+        mux.push(1, &input());
+
+        let mut out = Vec::new();
+        assert_eq!(mux.pull(10, &mut out), 0);
+        assert_eq!(out.len(), 10 * TS_PACKET_SIZE);
+
+        let packets: Vec<TsPacket> = out.chunks(TS_PACKET_SIZE).map(|p| TsPacket::new(p).unwrap()).collect();
+        let pids: Vec<u16> = packets.iter().map(|ts| ts.get_pid()).collect();
+        // PMT are sent with PAT before elementary streams
+        assert_eq!(&pids[.. 5], &[0x0000, 0x1000, 0x20, 0x100, 0x21]);
+        assert_eq!(pids[5], NULL_PID);
+
+        let pat = Pat::parse(&Psi::default().push(&packets[0])[0]).unwrap();
+        assert_eq!(pat.items, vec![
+            PatItem { pnr: 1, pid: 0x1000 },
+            PatItem { pnr: 2, pid: 0x20 },
+        ]);
+    }
+
+    #[test]
+    fn tables() {
+        let mut mux = Multiplexer::new(1, 2, 1_000_000);
+        mux.set_network(3, "Network");
+        mux.set_intervals(Intervals {
+            pat: Duration::from_millis(100),
+            pmt: Duration::from_millis(200),
+            .. Intervals::default()
+        });
+        mux.push(0, &input());
+
+        // 1 second of the output
+        let mut out = Vec::new();
+        mux.pull(1_000_000 / (TS_PACKET_SIZE * 8), &mut out);
+
+        let packets: Vec<TsPacket> = TsIter::new(&out).collect();
+        let count = |pid| packets.iter().filter(|ts| ts.get_pid() == pid).count();
+        assert_eq!(count(PAT_PID), 10);
+        assert_eq!(count(0x1000), 5);
+        assert_eq!(count(NIT_PID), 1);
+        assert_eq!(count(SDT_PID), 0);
+
+        let pat = Pat::parse(&Psi::default().push(&packets[0])[0]).unwrap();
+        assert_eq!(pat.items, vec![
+            PatItem { pnr: 0, pid: NIT_PID },
+            PatItem { pnr: 1, pid: 0x1000 },
+        ]);
+
+        let nit = packets.iter().find(|ts| ts.get_pid() == NIT_PID).unwrap();
+        let nit = Nit::parse(&Psi::default().push(nit)[0]).unwrap();
+        assert_eq!(nit.header.table_id_extension, 3);
+        assert_eq!(nit.get_name().as_deref(), Some("Network"));
+        assert_eq!(nit.items[0].tsid, 1);
+        assert_eq!(nit.items[0].onid, 2);
+        assert_eq!(nit.items[0].descriptors, vec![0x41, 0x03, 0x00, 0x01, 0x01]);
+    }
+
+
+    /// Returns PAT with 200 programs.
+    fn programs(mut cc: u8) -> Vec<u8> {
+        let pat = Pat {
+            header: SectionHeader { table_id: 0x00, table_id_extension: 1, .. Default::default() },
+            items: (1 ..= 200).map(|pnr| PatItem { pnr, pid: 0x100 + pnr }).collect(),
+        };
+
+        let mut out = Vec::new();
+        packetize(&pat.build(), 0, &mut cc, &mut out);
+        out
+    }
+
+    #[test]
+    fn split_tables() {
+        let mut mux = Multiplexer::new(1, 2, 10_000_000);
+        mux.set_network(3, "Network");
+
+        // 3 inputs with 200 programs each
+        for id in 0 .. 3 {
+            mux.push(id, &programs(0));
+        }
+
+        let mut out = Vec::new();
+        mux.pull(100, &mut out);
+        assert!(! mux.changed);
+
+        let mut pat = Psi::default();
+        let mut nit = Psi::default();
+        let mut pat_sections = Vec::new();
+        let mut nit_sections = Vec::new();
+        for ts in TsIter::new(&out) {
+            match ts.get_pid() {
+                PAT_PID => pat_sections.extend(pat.push(&ts)),
+                NIT_PID => nit_sections.extend(nit.push(&ts)),
+                _ => {}
+            }
+        }
+
+        let pat_sections: Vec<Pat> = pat_sections.iter().map(|s| Pat::parse(s).unwrap()).collect();
+        assert_eq!(pat_sections.len(), 3);
+        assert!(pat_sections.iter().enumerate().all(|(i, pat)| {
+            usize::from(pat.header.section_number) == i && pat.header.last_section_number == 2
+        }));
+        // NIT program and 600 programs of the inputs
+        assert_eq!(pat_sections.iter().map(|pat| pat.items.len()).sum::<usize>(), 601);
+
+        let nit_sections: Vec<Nit> = nit_sections.iter().map(|s| Nit::parse(s).unwrap()).collect();
+        // 8 service_list_descriptors, up to 3 in the section
+        assert_eq!(nit_sections.len(), 3);
+        assert!(nit_sections.iter().all(|nit| nit.get_name().as_deref() == Some("Network")));
+        // 3 bytes of the service_list_descriptor item per program
+        let services: usize = nit_sections.iter()
+            .flat_map(|nit| descriptors(&nit.items[0].descriptors).map(|(_, data)| data.len() / 3))
+            .sum();
+        assert_eq!(services, 600);
+
+        // tables are rebuilt only if inputs are changed
+        mux.push(0, &programs(1));
+        assert!(! mux.changed);
+
+        mux.close(2);
+        assert!(mux.changed);
+    }
+
+
+    /// Returns PAT of the input with given programs.
+    fn pat(version: u8, items: Vec<PatItem>) -> Vec<u8> {
+        let pat = Pat {
+            header: SectionHeader { table_id: 0x00, table_id_extension: 1, version, .. Default::default() },
+            items,
+        };
+
+        let mut cc = version;
+        let mut out = Vec::new();
+        packetize(&pat.build(), 0, &mut cc, &mut out);
+        out
+    }
+
+    /// Returns sections of the PID in the output.
+    fn sections(out: &[u8], pid: u16) -> Vec<Vec<u8>> {
+        let mut psi = Psi::default();
+        TsIter::new(out).filter(|ts| ts.get_pid() == pid).flat_map(|ts| psi.push(&ts)).collect()
+    }
+
+    #[test]
+    fn ca_pid() {
+        // PMT of program 1: CA_descriptor with ECM on PID 0x101, video on PID 0x100
+        let pmt = SectionHeader { table_id: 0x02, table_id_extension: 1, .. Default::default() }
+            .build(&[
+                0xE1, 0x00, 0xF0, 0x06, 0x09, 0x04, 0x0B, 0x00, 0xE1, 0x01,
+                0x1B, 0xE1, 0x00, 0xF0, 0x00,
+            ]);
+
+        let mut data = pat(0, vec![PatItem { pnr: 1, pid: 0x1000 }]);
+        packetize(&pmt, 0x1000, &mut 0, &mut data);
+        packetize(&[0x00; 4], 0x101, &mut 0, &mut data);
+
+        let mut mux = Multiplexer::new(1, 1, 1_000_000);
+        mux.push(0, &data);
+        mux.push(1, &data);
+
+        let mut out = Vec::new();
+        mux.pull(10, &mut out);
+
+        // ECM of the second input is moved with CA_PID in the PMT
+        let pmt = Pmt::parse(&sections(&out, 0x20)[0]).unwrap();
+        let (_, ca) = descriptors(&pmt.descriptors).next().unwrap();
+        assert_eq!(get_ca_pid(ca), Some(0x22));
+        assert_eq!(pmt.items[0].pid, 0x21);
+        assert!(TsIter::new(&out).any(|ts| ts.get_pid() == 0x22));
+    }
+
+    #[test]
+    fn release_program() {
+        let mut mux = Multiplexer::new(1, 1, 1_000_000);
+        mux.push(0, &input());
+        mux.push(1, &input());
+
+        // program of the second input is removed, its program number and PIDs are free
+        mux.push(1, &pat(1, Vec::new()));
+        mux.push(2, &input());
+
+        let mut out = Vec::new();
+        mux.pull(10, &mut out);
+
+        let pat = Pat::parse(&sections(&out, PAT_PID)[0]).unwrap();
+        assert_eq!(pat.items, vec![
+            PatItem { pnr: 1, pid: 0x1000 },
+            PatItem { pnr: 2, pid: 0x20 },
+        ]);
+    }
+}
@@ -0,0 +1,157 @@
+use {
+    std::{
+        env,
+        io,
+        os::{
+            linux::net::SocketAddrExt,
+            unix::net::{
+                SocketAddr,
+                UnixDatagram,
+            },
+        },
+        time::Duration,
+    },
+
+    log::warn,
+};
+
+
+/// Returns CLOCK_MONOTONIC in microseconds.
+fn monotonic_usec() -> u64 {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    // SAFETY: `ts` is a valid pointer for the duration of the call
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1_000
+}
+
+
+/// Service state notifications to the systemd.
+/// See sd_notify(3). Notifications are ignored if `NOTIFY_SOCKET` is not defined.
+#[derive(Default)]
+pub struct Notifier {
+    socket: Option<(UnixDatagram, SocketAddr)>,
+    watchdog: Option<Duration>,
+}
+
+impl Notifier {
+    /// Creates notifier with the socket path. Path started with `@` is an abstract socket.
+    /// `watchdog` is a watchdog timeout defined for the service.
+    pub fn new(path: &str, watchdog: Option<Duration>) -> io::Result<Self> {
+        let address = match path.strip_prefix('@') {
+            Some(name) => SocketAddr::from_abstract_name(name)?,
+            None => SocketAddr::from_pathname(path)?,
+        };
+
+        Ok(Self {
+            socket: Some((UnixDatagram::unbound()?, address)),
+            watchdog,
+        })
+    }
+
+    /// Creates notifier from the `NOTIFY_SOCKET`, `WATCHDOG_USEC` and `WATCHDOG_PID`
+    /// environment variables.
+    pub fn from_env() -> Self {
+        let path = match env::var("NOTIFY_SOCKET") {
+            Ok(v) => v,
+            Err(_) => return Self::default(),
+        };
+
+        let watchdog = env::var("WATCHDOG_USEC").ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|_| match env::var("WATCHDOG_PID") {
+                Ok(pid) => pid == std::process::id().to_string(),
+                Err(_) => true,
+            })
+            .map(Duration::from_micros);
+
+        Self::new(&path, watchdog).unwrap_or_else(|err| {
+            warn!("failed to open notify socket \"{}\": {}", path, err);
+            Self::default()
+        })
+    }
+
+    /// Interval for the watchdog keep-alive pings. Half of the watchdog timeout.
+    #[inline]
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog.map(|timeout| timeout / 2)
+    }
+
+    /// Sends newline-separated list of variable assignments.
+    pub fn notify(&self, state: &str) {
+        if let Some((socket, address)) = &self.socket {
+            if let Err(err) = socket.send_to_addr(state.as_bytes(), address) {
+                warn!("failed to send notification: {}", err);
+            }
+        }
+    }
+
+    /// Service startup is finished.
+    #[inline]
+    pub fn ready(&self) {
+        self.notify("READY=1")
+    }
+
+    /// Service is reloading its configuration. `ready()` should be called on complete.
+    #[inline]
+    pub fn reloading(&self) {
+        self.notify(&format!("RELOADING=1\nMONOTONIC_USEC={}", monotonic_usec()))
+    }
+
+    #[inline]
+    pub fn stopping(&self) {
+        self.notify("STOPPING=1")
+    }
+
+    /// Updates status description of the service.
+    #[inline]
+    pub fn status(&self, status: &str) {
+        self.notify(&format!("STATUS={}", status))
+    }
+
+    /// Watchdog keep-alive ping.
+    #[inline]
+    pub fn watchdog(&self) {
+        self.notify("WATCHDOG=1")
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        std::{
+            fs,
+            time::Duration,
+            os::unix::net::UnixDatagram,
+        },
+
+        super::Notifier,
+    };
+
+
+    #[test]
+    fn notify() {
+        let path = std::env::temp_dir().join(format!("tsplay-notify-{}.sock", std::process::id()));
+        fs::remove_file(&path).ok();
+        let socket = UnixDatagram::bind(&path).unwrap();
+
+        let notifier = Notifier::new(path.to_str().unwrap(), Some(Duration::from_secs(10))).unwrap();
+        assert_eq!(notifier.watchdog_interval(), Some(Duration::from_secs(5)));
+
+        let mut buf = [0; 256];
+
+        notifier.ready();
+        let size = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[.. size], b"READY=1");
+
+        notifier.status("1 running");
+        let size = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[.. size], b"STATUS=1 running");
+
+        notifier.reloading();
+        let size = socket.recv(&mut buf).unwrap();
+        assert!(buf[.. size].starts_with(b"RELOADING=1\nMONOTONIC_USEC="));
+
+        fs::remove_file(&path).ok();
+    }
+}
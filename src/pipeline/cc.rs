@@ -0,0 +1,230 @@
+use {
+    std::{
+        sync::Arc,
+        collections::HashMap,
+    },
+
+    crate::{
+        ts::{
+            TsPacket,
+            TsPacketMut,
+            NULL_PID,
+        },
+        stats::Stats,
+    },
+
+    super::Process,
+};
+
+
+struct CcState {
+    /// continuity_counter of the last packet
+    cc: u8,
+    /// Last packet has payload
+    payload: bool,
+    /// Last packet is a duplicate
+    duplicate: bool,
+}
+
+
+/// Result of the continuity_counter check.
+#[derive(Debug, PartialEq)]
+pub enum Continuity {
+    Ok,
+    /// First duplicate of the previous packet
+    Duplicate,
+    /// Discontinuity signalled with discontinuity_indicator
+    Discontinuity,
+    /// Lost packet, incorrect order or packet occurs more than twice
+    Error,
+}
+
+
+/// Checks continuity_counter of each PID according to ISO/IEC 13818-1.
+#[derive(Default)]
+pub struct CcTracker {
+    pids: HashMap<u16, CcState>,
+}
+
+impl CcTracker {
+    /// Checks continuity_counter of the packet. First packet of the PID and null packets are always `Ok`.
+    pub fn check(&mut self, ts: &TsPacket) -> Continuity {
+        let pid = ts.get_pid();
+        let cc = ts.get_cc();
+        let payload = ts.is_payload();
+
+        if pid == NULL_PID {
+            return Continuity::Ok
+        }
+
+        let state = match self.pids.get_mut(&pid) {
+            Some(v) => v,
+            None => {
+                self.pids.insert(pid, CcState { cc, payload, duplicate: false });
+                return Continuity::Ok
+            }
+        };
+
+        let continuity = if ts.is_discontinuity() {
+            Continuity::Discontinuity
+        } else if ! payload {
+            // continuity_counter is not incremented without payload
+            if cc == state.cc { Continuity::Ok } else { Continuity::Error }
+        } else if cc == state.cc && state.payload && ! state.duplicate {
+            Continuity::Duplicate
+        } else if cc == (state.cc + 1) & 0x0F {
+            Continuity::Ok
+        } else {
+            Continuity::Error
+        };
+
+        state.cc = cc;
+        state.payload = payload;
+        state.duplicate = continuity == Continuity::Duplicate;
+
+        continuity
+    }
+}
+
+
+/// Counts continuity_counter errors, duplicate packets and signalled discontinuities of the input
+/// in the stream statistics. Packets are checked in place, without copying.
+pub struct ContinuityCheck {
+    stats: Arc<Stats>,
+    tracker: CcTracker,
+}
+
+impl ContinuityCheck {
+    /// Errors are counted in `stats`.
+    pub fn new(stats: Arc<Stats>) -> Self {
+        Self {
+            stats,
+            tracker: CcTracker::default(),
+        }
+    }
+
+    /// Checks continuity_counter of the packet.
+    pub fn push(&mut self, ts: &TsPacket) {
+        let pid = ts.get_pid();
+        match self.tracker.check(ts) {
+            Continuity::Ok => {}
+            Continuity::Duplicate => self.stats.update_pid(pid, |s| s.cc_duplicates += 1),
+            Continuity::Discontinuity => self.stats.update_pid(pid, |s| s.cc_discontinuities += 1),
+            Continuity::Error => self.stats.update_pid(pid, |s| s.cc_errors += 1),
+        }
+    }
+}
+
+
+/// Renumbers continuity_counter on the output.
+/// Duplicate packets keep continuity_counter of the previous packet.
+#[derive(Default)]
+pub struct CcRepair {
+    tracker: CcTracker,
+    /// continuity_counter of the last output packet by PID
+    out_cc: HashMap<u16, u8>,
+}
+
+impl Process for CcRepair {
+    fn process(&mut self, ts: &TsPacket, out: &mut Vec<u8>) {
+        let pid = ts.get_pid();
+        let continuity = self.tracker.check(ts);
+
+        let mut packet = TsPacketMut::push_packet(out, ts);
+
+        if pid == NULL_PID {
+            return
+        }
+
+        let cc = ts.get_cc();
+        let payload = ts.is_payload();
+        let out_cc = self.out_cc.entry(pid).or_insert(if payload { cc.wrapping_sub(1) & 0x0F } else { cc });
+        if payload && continuity != Continuity::Duplicate {
+            *out_cc = (*out_cc + 1) & 0x0F;
+        }
+        packet.set_cc(*out_cc);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        std::sync::Arc,
+
+        crate::{
+            ts::{
+                TsPacket,
+                TS_PACKET_SIZE,
+            },
+            stats::Stats,
+        },
+
+        super::{
+            ContinuityCheck,
+            CcRepair,
+            Process,
+        },
+    };
+
+
+    /// Returns TS packet on PID 0x100. Packet without payload has adaptation field only.
+    fn packet(cc: u8, payload: bool, discontinuity: bool) -> Vec<u8> {
+        let mut ts = vec![0x47, 0x01, 0x00, 0x30 | cc, 1, 0x00];
+        if ! payload {
+            ts[3] = 0x20 | cc;
+            ts[4] = 183;
+        }
+        if discontinuity {
+            ts[5] = 0x80;
+        }
+        ts.resize(TS_PACKET_SIZE, 0xFF);
+        ts
+    }
+
+    fn input() -> Vec<Vec<u8>> {
+        vec![
+            packet(0, true, false),
+            packet(1, true, false),
+            // duplicate
+            packet(1, true, false),
+            // adaptation only
+            packet(1, false, false),
+            // error: 2 is lost
+            packet(3, true, false),
+            // second duplicate is an error
+            packet(3, true, false),
+            packet(3, true, false),
+            // signalled discontinuity
+            packet(9, true, true),
+            packet(10, true, false),
+        ]
+    }
+
+    #[test]
+    fn detect() {
+        let stats = Arc::new(Stats::default());
+        let mut check = ContinuityCheck::new(stats.clone());
+        for ts in input() {
+            check.push(&TsPacket::new(&ts).unwrap());
+        }
+
+        let pids = stats.get_pids();
+        let pid = &pids[&0x100];
+        assert_eq!(pid.cc_duplicates, 2);
+        assert_eq!(pid.cc_errors, 2);
+        assert_eq!(pid.cc_discontinuities, 1);
+    }
+
+    #[test]
+    fn repair() {
+        let mut repair = CcRepair::default();
+        let mut out = Vec::new();
+        for ts in input() {
+            repair.process(&TsPacket::new(&ts).unwrap(), &mut out);
+        }
+
+        let cc: Vec<u8> = out.chunks(TS_PACKET_SIZE).map(|p| TsPacket::new(p).unwrap().get_cc()).collect();
+        assert_eq!(cc, vec![0, 1, 1, 1, 2, 2, 3, 4, 5]);
+    }
+}
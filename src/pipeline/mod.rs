@@ -0,0 +1,73 @@
+use {
+    std::mem,
+
+    crate::ts::{
+        TsPacket,
+        TsIter,
+    },
+};
+
+mod cc;
+pub use cc::{
+    ContinuityCheck,
+    CcRepair,
+    CcTracker,
+    Continuity,
+};
+
+mod pid;
+pub use pid::PidMap;
+
+mod program;
+pub use program::ProgramSelect;
+
+mod null;
+pub use null::NullStrip;
+
+mod stuffing;
+pub use stuffing::Stuffing;
+
+
+/// Packet processing stage.
+pub trait Process: Send {
+    /// Handles single TS packet. Resulting packets should be appended to the `out`.
+    /// Stage may drop packet, modify it, insert additional packets, or keep it for later.
+    fn process(&mut self, ts: &TsPacket, out: &mut Vec<u8>);
+}
+
+
+/// Chain of the processing stages applied to each packet of the stream.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Process>>,
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl Pipeline {
+    /// Appends processing stage to the end of the chain.
+    pub fn push<P: Process + 'static>(&mut self, stage: P) {
+        self.stages.push(Box::new(stage));
+    }
+
+    /// Passes TS packet through all stages and appends result to the `out`.
+    pub fn process(&mut self, ts: &TsPacket, out: &mut Vec<u8>) {
+        if self.stages.is_empty() {
+            out.extend_from_slice(ts.as_bytes());
+            return
+        }
+
+        self.input.clear();
+        self.input.extend_from_slice(ts.as_bytes());
+
+        for stage in self.stages.iter_mut() {
+            self.output.clear();
+            for ts in TsIter::new(&self.input) {
+                stage.process(&ts, &mut self.output);
+            }
+            mem::swap(&mut self.input, &mut self.output);
+        }
+
+        out.extend_from_slice(&self.input);
+    }
+}
@@ -0,0 +1,85 @@
+use {
+    std::sync::{
+        Arc,
+        atomic::Ordering,
+    },
+
+    crate::{
+        ts::{
+            TsPacket,
+            NULL_PID,
+        },
+        stats::Stats,
+    },
+
+    super::Process,
+};
+
+
+/// Removes null packets from the stream.
+pub struct NullStrip {
+    stats: Arc<Stats>,
+}
+
+impl NullStrip {
+    /// Removed packets are counted in `stats`.
+    pub fn new(stats: Arc<Stats>) -> Self {
+        Self { stats }
+    }
+}
+
+impl Process for NullStrip {
+    #[inline]
+    fn process(&mut self, ts: &TsPacket, out: &mut Vec<u8>) {
+        if ts.get_pid() == NULL_PID {
+            self.stats.null_removed.fetch_add(1, Ordering::Relaxed);
+        } else {
+            out.extend_from_slice(ts.as_bytes());
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        std::sync::{
+            Arc,
+            atomic::Ordering,
+        },
+
+        crate::{
+            ts::{
+                TsIter,
+                TsPacketMut,
+                NULL_PID,
+            },
+            stats::Stats,
+        },
+
+        super::{
+            NullStrip,
+            Process,
+        },
+    };
+
+
+    #[test]
+    fn strip() {
+        let mut data = Vec::new();
+        for pid in [0x100, NULL_PID, NULL_PID, 0x101, NULL_PID] {
+            TsPacketMut::push(&mut data).set_pid(pid);
+        }
+
+        let stats = Arc::new(Stats::default());
+        let mut strip = NullStrip::new(stats.clone());
+        let mut out = Vec::new();
+        for ts in TsIter::new(&data) {
+            strip.process(&ts, &mut out);
+        }
+
+        let pids: Vec<u16> = TsIter::new(&out).map(|ts| ts.get_pid()).collect();
+        assert_eq!(pids, vec![0x100, 0x101]);
+        assert_eq!(stats.null_removed.load(Ordering::Relaxed), 3);
+    }
+}
@@ -0,0 +1,286 @@
+use {
+    std::collections::{
+        HashMap,
+        HashSet,
+    },
+
+    crate::{
+        ts::{
+            TsPacket,
+            TsPacketMut,
+            NULL_PID,
+        },
+        psi::{
+            Psi,
+            Pat,
+            Pmt,
+            SectionHeader,
+            PAT_PID,
+            CAT_PID,
+            CAT_TABLE_ID,
+            map_ca_pids,
+            is_valid_section,
+            packetize,
+        },
+    },
+
+    super::Process,
+};
+
+
+/// Drops and renumbers PIDs. PAT, PMT and CAT are rewritten to keep output consistent.
+/// CA_descriptors with filtered CA_PID are removed, others are renumbered.
+#[derive(Default)]
+pub struct PidMap {
+    filter: HashSet<u16>,
+    remap: HashMap<u16, u16>,
+
+    pat: Psi,
+    pat_cc: u8,
+    cat: Psi,
+    cat_cc: u8,
+    /// PMT assemblers and continuity counters by the original PMT PID
+    pmt: HashMap<u16, (Psi, u8)>,
+}
+
+impl PidMap {
+    /// `filter` is a list of PIDs to drop, `remap` is a list of pairs `(from, to)`.
+    pub fn new<F, R>(filter: F, remap: R) -> Self
+    where
+        F: IntoIterator<Item = u16>,
+        R: IntoIterator<Item = (u16, u16)>,
+    {
+        Self {
+            filter: filter.into_iter().collect(),
+            remap: remap.into_iter().collect(),
+            .. Default::default()
+        }
+    }
+
+    /// Returns `true` if there is nothing to do.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.filter.is_empty() && self.remap.is_empty()
+    }
+
+    #[inline]
+    fn map(&self, pid: u16) -> u16 {
+        self.remap.get(&pid).copied().unwrap_or(pid)
+    }
+
+    /// Rewrites CA_PID in the CA_descriptors of the descriptors loop.
+    fn map_descriptors(&self, data: &[u8]) -> Vec<u8> {
+        map_ca_pids(data, |pid| if self.filter.contains(&pid) { None } else { Some(self.map(pid)) })
+    }
+
+    fn on_cat(&mut self, section: &[u8], out: &mut Vec<u8>) {
+        if ! is_valid_section(section) || section[0] != CAT_TABLE_ID {
+            return
+        }
+
+        let header = SectionHeader::parse(section);
+        let data = self.map_descriptors(&section[8 .. section.len() - 4]);
+
+        packetize(&header.build(&data), CAT_PID, &mut self.cat_cc, out);
+    }
+
+    fn on_pat(&mut self, section: &[u8], out: &mut Vec<u8>) {
+        let mut pat = match Pat::parse(section) {
+            Some(v) => v,
+            None => return,
+        };
+
+        let filter = &self.filter;
+        pat.items.retain(|item| ! filter.contains(&item.pid));
+
+        let mut pmt = HashMap::new();
+        for item in pat.items.iter_mut() {
+            if item.pnr != 0 {
+                let state = self.pmt.remove(&item.pid).unwrap_or_default();
+                pmt.insert(item.pid, state);
+            }
+            item.pid = self.map(item.pid);
+        }
+        self.pmt = pmt;
+
+        packetize(&pat.build(), PAT_PID, &mut self.pat_cc, out);
+    }
+
+    fn on_pmt(&mut self, pid: u16, section: &[u8], out: &mut Vec<u8>) {
+        let mut pmt = match Pmt::parse(section) {
+            Some(v) => v,
+            None => return,
+        };
+
+        let filter = &self.filter;
+        pmt.items.retain(|item| ! filter.contains(&item.pid));
+
+        pmt.descriptors = self.map_descriptors(&pmt.descriptors);
+        for item in pmt.items.iter_mut() {
+            item.pid = self.map(item.pid);
+            item.descriptors = self.map_descriptors(&item.descriptors);
+        }
+
+        pmt.pcr_pid = if self.filter.contains(&pmt.pcr_pid) {
+            NULL_PID
+        } else {
+            self.map(pmt.pcr_pid)
+        };
+
+        let to = self.map(pid);
+        if let Some((_, cc)) = self.pmt.get_mut(&pid) {
+            packetize(&pmt.build(), to, cc, out);
+        }
+    }
+}
+
+impl Process for PidMap {
+    fn process(&mut self, ts: &TsPacket, out: &mut Vec<u8>) {
+        let pid = ts.get_pid();
+
+        if self.filter.contains(&pid) {
+            return
+        }
+
+        if pid == PAT_PID {
+            for section in self.pat.push(ts) {
+                self.on_pat(&section, out);
+            }
+            return
+        }
+
+        if pid == CAT_PID {
+            for section in self.cat.push(ts) {
+                self.on_cat(&section, out);
+            }
+            return
+        }
+
+        if let Some((psi, _)) = self.pmt.get_mut(&pid) {
+            for section in psi.push(ts) {
+                self.on_pmt(pid, &section, out);
+            }
+            return
+        }
+
+        let mut packet = TsPacketMut::push_packet(out, ts);
+        if let Some(&to) = self.remap.get(&pid) {
+            packet.set_pid(to);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::{
+            ts::{
+                TsPacket,
+                TS_PACKET_SIZE,
+            },
+            psi::{
+                Psi,
+                Pat,
+                Pmt,
+                SectionHeader,
+                descriptors,
+                get_ca_pid,
+                packetize,
+            },
+        },
+
+        super::{
+            PidMap,
+            Process,
+        },
+    };
+
+
+    fn stream() -> Vec<u8> {
+        // PAT: program 1 on PID 0x1000, program 2 on PID 0x1200
+        let pat = SectionHeader { table_id: 0x00, table_id_extension: 1, .. Default::default() }
+            .build(&[0x00, 0x01, 0xF0, 0x00, 0x00, 0x02, 0xF2, 0x00]);
+
+        // PMT: PCR on PID 0x100, video on PID 0x100, audio on PID 0x101
+        let pmt = SectionHeader { table_id: 0x02, table_id_extension: 1, .. Default::default() }
+            .build(&[0xE1, 0x00, 0xF0, 0x00, 0x1B, 0xE1, 0x00, 0xF0, 0x00, 0x03, 0xE1, 0x01, 0xF0, 0x00]);
+
+        let mut out = Vec::new();
+        packetize(&pat, 0, &mut 0, &mut out);
+        packetize(&pmt, 0x1000, &mut 0, &mut out);
+        for pid in [0x100, 0x101] {
+            packetize(&[0x00; 4], pid, &mut 0, &mut out);
+        }
+        out
+    }
+
+    fn process(map: &mut PidMap, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for packet in data.chunks(TS_PACKET_SIZE) {
+            map.process(&TsPacket::new(packet).unwrap(), &mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn filter_remap() {
+        let mut map = PidMap::new(vec![0x101, 0x1200], vec![(0x100, 0x200), (0x1000, 0x1100)]);
+        let out = process(&mut map, &stream());
+        assert_eq!(out.len(), 3 * TS_PACKET_SIZE);
+
+        let packets: Vec<TsPacket> = out.chunks(TS_PACKET_SIZE).map(|p| TsPacket::new(p).unwrap()).collect();
+
+        let pat = Pat::parse(&Psi::default().push(&packets[0])[0]).unwrap();
+        assert_eq!(pat.items.len(), 1);
+        assert_eq!(pat.items[0].pnr, 1);
+        assert_eq!(pat.items[0].pid, 0x1100);
+
+        assert_eq!(packets[1].get_pid(), 0x1100);
+        let pmt = Pmt::parse(&Psi::default().push(&packets[1])[0]).unwrap();
+        assert_eq!(pmt.pcr_pid, 0x200);
+        assert_eq!(pmt.items.len(), 1);
+        assert_eq!(pmt.items[0].pid, 0x200);
+
+        assert_eq!(packets[2].get_pid(), 0x200);
+    }
+
+    #[test]
+    fn ca_pid() {
+        let pat = SectionHeader { table_id: 0x00, table_id_extension: 1, .. Default::default() }
+            .build(&[0x00, 0x01, 0xF0, 0x00]);
+
+        // PMT: ECM on PID 0x150 for the program and on PID 0x151 for the video
+        let pmt = SectionHeader { table_id: 0x02, table_id_extension: 1, .. Default::default() }
+            .build(&[
+                0xE1, 0x00, 0xF0, 0x06, 0x09, 0x04, 0x0B, 0x00, 0xE1, 0x50,
+                0x1B, 0xE1, 0x00, 0xF0, 0x06, 0x09, 0x04, 0x0B, 0x00, 0xE1, 0x51,
+            ]);
+
+        // CAT: EMM on PIDs 0x160 and 0x161
+        let cat = SectionHeader { table_id: 0x01, table_id_extension: 0xFFFF, .. Default::default() }
+            .build(&[0x09, 0x04, 0x0B, 0x00, 0xE1, 0x60, 0x09, 0x04, 0x0B, 0x00, 0xE1, 0x61]);
+
+        let mut data = Vec::new();
+        packetize(&pat, 0, &mut 0, &mut data);
+        packetize(&cat, 1, &mut 0, &mut data);
+        packetize(&pmt, 0x1000, &mut 0, &mut data);
+
+        let mut map = PidMap::new(vec![0x151, 0x161], vec![(0x150, 0x250), (0x160, 0x260)]);
+        let out = process(&mut map, &data);
+        let packets: Vec<TsPacket> = out.chunks(TS_PACKET_SIZE).map(|p| TsPacket::new(p).unwrap()).collect();
+        assert_eq!(packets.len(), 3);
+
+        let ca_pids = |data: &[u8]| -> Vec<u16> {
+            descriptors(data).filter_map(|(_, data)| get_ca_pid(data)).collect()
+        };
+
+        assert_eq!(packets[1].get_pid(), 1);
+        let cat = Psi::default().push(&packets[1]).remove(0);
+        assert_eq!(ca_pids(&cat[8 .. cat.len() - 4]), vec![0x260]);
+
+        let pmt = Pmt::parse(&Psi::default().push(&packets[2])[0]).unwrap();
+        assert_eq!(ca_pids(&pmt.descriptors), vec![0x250]);
+        assert!(pmt.items[0].descriptors.is_empty());
+    }
+}
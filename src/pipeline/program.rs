@@ -0,0 +1,382 @@
+use {
+    std::collections::HashSet,
+
+    crate::{
+        ts::{
+            TsPacket,
+            NULL_PID,
+        },
+        psi::{
+            Psi,
+            Pat,
+            PatItem,
+            Pmt,
+            Sdt,
+            SectionHeader,
+            packetize,
+            is_valid_section,
+            is_eit_actual,
+            descriptors,
+            get_ca_pid,
+            CA_DESCRIPTOR,
+            PAT_PID,
+            NIT_PID,
+            SDT_PID,
+            EIT_PID,
+            TDT_PID,
+            SDT_ACTUAL_TABLE_ID,
+        },
+    },
+
+    super::Process,
+};
+
+
+/// Extracts single program from the multi-program transport stream.
+///
+/// Program is selected by program_number, by service name from the SDT,
+/// or the first program in the PAT if neither is defined.
+#[derive(Default)]
+pub struct ProgramSelect {
+    pnr: Option<u16>,
+    name: Option<String>,
+    si: bool,
+
+    pat: Psi,
+    pat_cc: u8,
+
+    pmt_pid: Option<u16>,
+    pmt: Psi,
+    /// Elementary stream, PCR and ECM PIDs of the selected program
+    pids: HashSet<u16>,
+
+    sdt: Psi,
+    sdt_cc: u8,
+    eit: Psi,
+    eit_cc: u8,
+}
+
+impl ProgramSelect {
+    /// Creates new program selector.
+    /// If `si` is `true` then NIT, TDT/TOT and SDT/EIT of the selected program are kept.
+    pub fn new(pnr: Option<u16>, name: Option<String>, si: bool) -> Self {
+        Self {
+            pnr,
+            name,
+            si,
+            .. Default::default()
+        }
+    }
+
+    fn on_pat(&mut self, section: &[u8], out: &mut Vec<u8>) {
+        let pat = match Pat::parse(section) {
+            Some(v) => v,
+            None => return,
+        };
+
+        if self.pnr.is_none() && self.name.is_none() {
+            self.pnr = pat.items.iter().find(|item| item.pnr != 0).map(|item| item.pnr);
+        }
+
+        let pnr = match self.pnr {
+            Some(v) => v,
+            None => return,
+        };
+
+        let pmt_pid = match pat.items.iter().find(|item| item.pnr == pnr) {
+            Some(item) => item.pid,
+            None => return,
+        };
+
+        if self.pmt_pid != Some(pmt_pid) {
+            self.pmt_pid = Some(pmt_pid);
+            self.pmt = Psi::default();
+            self.pids.clear();
+        }
+
+        let mut items = Vec::new();
+        if self.si {
+            items.extend(pat.items.iter().filter(|item| item.pnr == 0).cloned());
+        }
+        items.push(PatItem { pnr, pid: pmt_pid });
+
+        let pat = Pat {
+            header: SectionHeader {
+                section_number: 0,
+                last_section_number: 0,
+                .. pat.header
+            },
+            items,
+        };
+
+        packetize(&pat.build(), PAT_PID, &mut self.pat_cc, out);
+    }
+
+    fn on_pmt(&mut self, section: &[u8]) {
+        let pmt = match Pmt::parse(section) {
+            Some(v) => v,
+            None => return,
+        };
+
+        if Some(pmt.header.table_id_extension) != self.pnr {
+            return
+        }
+
+        self.pids.clear();
+        if pmt.pcr_pid != NULL_PID {
+            self.pids.insert(pmt.pcr_pid);
+        }
+
+        let ecm = descriptors(&pmt.descriptors)
+            .chain(pmt.items.iter().flat_map(|item| descriptors(&item.descriptors)))
+            .filter(|(tag, _)| *tag == CA_DESCRIPTOR)
+            .filter_map(|(_, data)| get_ca_pid(data));
+        self.pids.extend(ecm);
+
+        self.pids.extend(pmt.items.iter().map(|item| item.pid));
+    }
+
+    fn on_sdt(&mut self, section: &[u8], out: &mut Vec<u8>) {
+        let mut sdt = match Sdt::parse(section) {
+            Some(v) => v,
+            None => return,
+        };
+
+        if sdt.header.table_id != SDT_ACTUAL_TABLE_ID {
+            return
+        }
+
+        if self.pnr.is_none() {
+            let name = self.name.as_deref();
+            self.pnr = sdt.items.iter()
+                .find(|item| item.get_name().as_deref() == name)
+                .map(|item| item.sid);
+        }
+
+        let pnr = match self.pnr {
+            Some(v) if self.si => v,
+            _ => return,
+        };
+
+        sdt.items.retain(|item| item.sid == pnr);
+        if sdt.items.is_empty() {
+            return
+        }
+
+        sdt.header.section_number = 0;
+        sdt.header.last_section_number = 0;
+
+        packetize(&sdt.build(), SDT_PID, &mut self.sdt_cc, out);
+    }
+
+    fn on_eit(&mut self, section: &[u8], out: &mut Vec<u8>) {
+        if ! is_valid_section(section) {
+            return
+        }
+
+        let header = SectionHeader::parse(section);
+        if is_eit_actual(header.table_id) && Some(header.table_id_extension) == self.pnr {
+            packetize(section, EIT_PID, &mut self.eit_cc, out);
+        }
+    }
+}
+
+impl Process for ProgramSelect {
+    fn process(&mut self, ts: &TsPacket, out: &mut Vec<u8>) {
+        let pid = ts.get_pid();
+
+        match pid {
+            PAT_PID => {
+                for section in self.pat.push(ts) {
+                    self.on_pat(&section, out);
+                }
+            }
+            SDT_PID => {
+                for section in self.sdt.push(ts) {
+                    self.on_sdt(&section, out);
+                }
+            }
+            EIT_PID if self.si => {
+                for section in self.eit.push(ts) {
+                    self.on_eit(&section, out);
+                }
+            }
+            NIT_PID | TDT_PID if self.si => {
+                out.extend_from_slice(ts.as_bytes());
+            }
+            _ if Some(pid) == self.pmt_pid => {
+                for section in self.pmt.push(ts) {
+                    self.on_pmt(&section);
+                }
+                out.extend_from_slice(ts.as_bytes());
+            }
+            _ if self.pids.contains(&pid) => {
+                out.extend_from_slice(ts.as_bytes());
+            }
+            _ => {}
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::{
+            ts::{
+                TsPacket,
+                TsPacketMut,
+                TsIter,
+                TS_PACKET_SIZE,
+                NULL_PID,
+            },
+            psi::{
+                Psi,
+                Pat,
+                PatItem,
+                Sdt,
+                SdtItem,
+                SectionHeader,
+                packetize,
+                PSI_MAX_SIZE,
+            },
+        },
+
+        super::{
+            ProgramSelect,
+            Process,
+        },
+    };
+
+
+    fn service(sid: u16, name: &str) -> SdtItem {
+        let mut descriptors = vec![0x48, 3 + name.len() as u8, 0x01, 0x00, name.len() as u8];
+        descriptors.extend_from_slice(name.as_bytes());
+
+        SdtItem {
+            sid,
+            eit_schedule: false,
+            eit_present_following: true,
+            running_status: 4,
+            free_ca: false,
+            descriptors,
+        }
+    }
+
+    fn stream() -> Vec<u8> {
+        let sdt = Sdt {
+            header: SectionHeader { table_id: 0x42, table_id_extension: 1, .. Default::default() },
+            onid: 1,
+            items: vec![service(1, "One"), service(2, "Two")],
+        };
+
+        let pat = Pat {
+            header: SectionHeader { table_id: 0x00, table_id_extension: 1, .. Default::default() },
+            items: vec![
+                PatItem { pnr: 0, pid: 0x10 },
+                PatItem { pnr: 1, pid: 0x1000 },
+                PatItem { pnr: 2, pid: 0x1001 },
+            ],
+        };
+
+        // PMT of program 1: video on PID 0x100
+        let pmt1 = SectionHeader { table_id: 0x02, table_id_extension: 1, .. Default::default() }
+            .build(&[0xE1, 0x00, 0xF0, 0x00, 0x1B, 0xE1, 0x00, 0xF0, 0x00]);
+        // PMT of program 2: video on PID 0x200, without PCR
+        let pmt2 = SectionHeader { table_id: 0x02, table_id_extension: 2, .. Default::default() }
+            .build(&[0xFF, 0xFF, 0xF0, 0x00, 0x1B, 0xE2, 0x00, 0xF0, 0x00]);
+
+        let mut out = Vec::new();
+        packetize(&sdt.build(), 0x11, &mut 0, &mut out);
+        packetize(&pat.build(), 0, &mut 0, &mut out);
+        packetize(&pmt1, 0x1000, &mut 0, &mut out);
+        packetize(&pmt2, 0x1001, &mut 0, &mut out);
+        // EIT present/following of both programs
+        let mut eit_cc = 0;
+        for sid in [1, 2] {
+            let eit = SectionHeader { table_id: 0x4E, table_id_extension: sid, .. Default::default() }
+                .build(&[0x00, 0x01, 0x00, 0x01, 0x01, 0x4E]);
+            packetize(&eit, 0x12, &mut eit_cc, &mut out);
+        }
+        for pid in [0x10, 0x14, 0x100, 0x200] {
+            packetize(&[0x00; 4], pid, &mut 0, &mut out);
+        }
+        TsPacketMut::push_null(&mut out);
+        out
+    }
+
+    fn run(select: &mut ProgramSelect) -> Vec<u8> {
+        let mut out = Vec::new();
+        for packet in stream().chunks(TS_PACKET_SIZE) {
+            select.process(&TsPacket::new(packet).unwrap(), &mut out);
+        }
+        out
+    }
+
+    fn pids(out: &[u8]) -> Vec<u16> {
+        TsIter::new(out).map(|ts| ts.get_pid()).collect()
+    }
+
+    /// Returns first section in the packet with given index.
+    fn section(out: &[u8], index: usize) -> Vec<u8> {
+        let ts = TsPacket::new(&out[index * TS_PACKET_SIZE ..]).unwrap();
+        Psi::default().push(&ts).remove(0)
+    }
+
+    #[test]
+    fn select_pnr() {
+        let out = run(&mut ProgramSelect::new(Some(2), None, false));
+        assert_eq!(pids(&out), vec![0x0000, 0x1001, 0x200]);
+        assert!(! pids(&out).contains(&NULL_PID));
+
+        let pat = Pat::parse(&section(&out, 0)).unwrap();
+        assert_eq!(pat.items, vec![PatItem { pnr: 2, pid: 0x1001 }]);
+    }
+
+    #[test]
+    fn select_name() {
+        let out = run(&mut ProgramSelect::new(None, Some("Two".to_owned()), false));
+        assert_eq!(pids(&out), vec![0x0000, 0x1001, 0x200]);
+    }
+
+    #[test]
+    fn si() {
+        let out = run(&mut ProgramSelect::new(Some(2), None, true));
+        assert_eq!(pids(&out), vec![0x11, 0x0000, 0x1001, 0x12, 0x10, 0x14, 0x200]);
+
+        let sdt = Sdt::parse(&section(&out, 0)).unwrap();
+        assert_eq!(sdt.items, vec![service(2, "Two")]);
+
+        let pat = Pat::parse(&section(&out, 1)).unwrap();
+        assert_eq!(pat.items, vec![PatItem { pnr: 0, pid: 0x10 }, PatItem { pnr: 2, pid: 0x1001 }]);
+
+        assert_eq!(SectionHeader::parse(&section(&out, 3)).table_id_extension, 2);
+    }
+
+
+    #[test]
+    fn large_eit() {
+        // EIT schedule with events up to the maximum size of the private section
+        let mut events = vec![0x00, 0x01, 0x00, 0x01, 0x01, 0x50];
+        events.resize(4000, 0x00);
+        let eit = SectionHeader { table_id: 0x50, table_id_extension: 2, .. Default::default() }
+            .build(&events);
+        assert!(eit.len() > PSI_MAX_SIZE);
+
+        let mut input = Vec::new();
+        packetize(&eit, 0x12, &mut 0, &mut input);
+
+        let mut select = ProgramSelect::new(Some(2), None, true);
+        let mut out = Vec::new();
+        for packet in stream().chunks(TS_PACKET_SIZE).chain(input.chunks(TS_PACKET_SIZE)) {
+            select.process(&TsPacket::new(packet).unwrap(), &mut out);
+        }
+
+        let mut psi = Psi::default();
+        let sections: Vec<Vec<u8>> = TsIter::new(&out)
+            .filter(|ts| ts.get_pid() == 0x12)
+            .flat_map(|ts| psi.push(&ts))
+            .collect();
+        assert_eq!(sections.last(), Some(&eit));
+    }
+}
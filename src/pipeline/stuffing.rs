@@ -0,0 +1,211 @@
+use {
+    std::mem,
+
+    log::warn,
+
+    crate::ts::{
+        TsPacket,
+        TsPacketMut,
+        TS_PACKET_SIZE,
+        PCR_CLOCK,
+        pcr_delta,
+        PcrRestamp,
+    },
+
+    super::Process,
+};
+
+
+/// Maximum PCR interval in 27MHz clocks. Larger gap is handled as discontinuity
+const MAX_PCR_DELTA: u64 = PCR_CLOCK;
+
+
+/// Converts variable bitrate stream to constant bitrate.
+///
+/// Packets between two PCRs are padded with null packets up to the configured bitrate.
+/// PCR values are restamped according to the packet position in the output.
+pub struct Stuffing {
+    name: String,
+    bitrate: u64,
+    restamp: PcrRestamp,
+
+    pcr_pid: Option<u16>,
+    pcr_last: u64,
+    /// Source clocks since first PCR
+    elapsed: u64,
+    /// Number of the output packets since first PCR
+    position: u64,
+
+    /// Packets since last PCR
+    pending: Vec<u8>,
+    /// Source bitrate exceeds configured bitrate
+    overflow: bool,
+}
+
+impl Stuffing {
+    /// `name` is a stream name for log messages, `bitrate` is an output bitrate in bits per second.
+    pub fn new(name: &str, bitrate: u64) -> Self {
+        Self {
+            name: name.to_owned(),
+            bitrate,
+            restamp: PcrRestamp::new(bitrate),
+
+            pcr_pid: None,
+            pcr_last: 0,
+            elapsed: 0,
+            position: 0,
+
+            pending: Vec::new(),
+            overflow: false,
+        }
+    }
+
+    /// Returns packet index at the given time in 27MHz clocks
+    #[inline]
+    fn time_to_position(&self, time: u64) -> u64 {
+        (u128::from(time) * u128::from(self.bitrate)
+            / (u128::from(PCR_CLOCK) * (TS_PACKET_SIZE as u128 * 8))) as u64
+    }
+
+    /// Appends packet to the output and restamps PCR
+    #[inline]
+    fn send(&mut self, packet: &[u8], out: &mut Vec<u8>) {
+        let start = out.len();
+        out.extend_from_slice(packet);
+        self.restamp.restamp(&mut out[start ..]);
+    }
+
+    fn reset(&mut self, pcr: u64, out: &mut Vec<u8>) {
+        let pending = mem::take(&mut self.pending);
+        for packet in pending.chunks(TS_PACKET_SIZE) {
+            self.send(packet, out);
+        }
+
+        self.pcr_last = pcr;
+        self.elapsed = 0;
+        self.position = 1;
+    }
+
+    fn on_pcr(&mut self, pcr: u64, out: &mut Vec<u8>) {
+        let delta = pcr_delta(self.pcr_last, pcr);
+        if self.position == 0 || delta > MAX_PCR_DELTA {
+            self.reset(pcr, out);
+            return
+        }
+
+        self.pcr_last = pcr;
+        self.elapsed += delta;
+
+        // index of the current PCR packet without stuffing
+        let count = (self.pending.len() / TS_PACKET_SIZE) as u64;
+        let index = self.position + count - 1;
+        let target = self.time_to_position(self.elapsed);
+
+        let nulls = if target > index {
+            self.overflow = false;
+            target - index
+        } else {
+            if ! self.overflow && target < index {
+                self.overflow = true;
+                warn!(stream = self.name.as_str(); "source bitrate exceeds output bitrate {}", self.bitrate);
+            }
+            0
+        };
+
+        // spread null packets evenly between pending packets
+        let mut null = Vec::with_capacity(TS_PACKET_SIZE);
+        TsPacketMut::push_null(&mut null);
+
+        let mut pending = mem::take(&mut self.pending);
+        for (i, packet) in pending.chunks(TS_PACKET_SIZE).enumerate() {
+            let i = i as u64;
+            for _ in (i * nulls / count) .. ((i + 1) * nulls / count) {
+                self.send(&null, out);
+            }
+            self.send(packet, out);
+        }
+
+        pending.clear();
+        self.pending = pending;
+        self.position += count + nulls;
+    }
+}
+
+impl Process for Stuffing {
+    fn process(&mut self, ts: &TsPacket, out: &mut Vec<u8>) {
+        let pid = ts.get_pid();
+
+        let pcr = match ts.get_pcr() {
+            Some(pcr) if self.pcr_pid.is_none() || self.pcr_pid == Some(pid) => {
+                self.pcr_pid = Some(pid);
+                pcr
+            }
+            _ => {
+                if self.pcr_pid.is_some() {
+                    self.pending.extend_from_slice(ts.as_bytes());
+                } else {
+                    self.send(ts.as_bytes(), out);
+                }
+                return
+            }
+        };
+
+        self.pending.extend_from_slice(ts.as_bytes());
+        self.on_pcr(pcr, out);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::ts::{
+            TsPacket,
+            TsPacketMut,
+            TS_PACKET_SIZE,
+            NULL_PID,
+        },
+
+        super::{
+            Stuffing,
+            Process,
+        },
+    };
+
+
+    fn packet(pid: u16, pcr: Option<u64>) -> Vec<u8> {
+        let mut ts = vec![0x47, (pid >> 8) as u8, pid as u8, 0x10];
+        ts.resize(TS_PACKET_SIZE, 0xFF);
+        if let Some(pcr) = pcr {
+            ts[3] = 0x30;
+            ts[4] = 7;
+            ts[5] = 0x10;
+            TsPacketMut::new(&mut ts).unwrap().set_pcr(Some(pcr));
+        }
+        ts
+    }
+
+    #[test]
+    fn stuffing() {
+        // 1 packet per millisecond
+        let bitrate = (TS_PACKET_SIZE * 8 * 1000) as u64;
+        let mut stuffing = Stuffing::new("test", bitrate);
+
+        let mut input = Vec::new();
+        input.extend(packet(0x100, Some(1_000_000)));
+        input.extend(packet(0x101, None));
+        input.extend(packet(0x101, None));
+        // 10ms later
+        input.extend(packet(0x100, Some(1_000_000 + 270_000)));
+
+        let mut out = Vec::new();
+        for chunk in input.chunks(TS_PACKET_SIZE) {
+            stuffing.process(&TsPacket::new(chunk).unwrap(), &mut out);
+        }
+
+        let packets: Vec<TsPacket> = out.chunks(TS_PACKET_SIZE).map(|p| TsPacket::new(p).unwrap()).collect();
+        assert_eq!(packets.len(), 11);
+        assert_eq!(packets.iter().filter(|ts| ts.get_pid() == NULL_PID).count(), 7);
+        assert_eq!(packets[10].get_pcr(), Some(1_000_000 + 270_000));
+    }
+}
@@ -0,0 +1,599 @@
+use {
+    std::{
+        fmt,
+        sync::Arc,
+        time::Duration,
+        collections::{
+            BTreeMap,
+            HashMap,
+        },
+    },
+
+    tokio::{
+        fs::File,
+        io::AsyncReadExt,
+        time::Instant,
+    },
+    serde::Serialize,
+
+    crate::{
+        error::Error,
+        ts::{
+            TsPacket,
+            TsIter,
+            TS_PACKET_SIZE,
+            NULL_PID,
+            PCR_CLOCK,
+            pcr_delta,
+        },
+        es::PesPacket,
+        psi::{
+            Psi,
+            Pat,
+            Pmt,
+            Sdt,
+            PAT_PID,
+            CAT_PID,
+            NIT_PID,
+            SDT_PID,
+            EIT_PID,
+            TDT_PID,
+            SDT_ACTUAL_TABLE_ID,
+        },
+        pipeline::{
+            CcTracker,
+            Continuity,
+        },
+        monitor::Monitor,
+        stats::Stats,
+    },
+};
+
+
+/// Maximum gap between timestamps in 27MHz clocks. Larger gap is handled as discontinuity
+const MAX_TIME_DELTA: u64 = PCR_CLOCK;
+/// Interval to measure peak bitrate in 27MHz clocks
+const PEAK_WINDOW: u64 = PCR_CLOCK;
+/// PTS wraps around after 2^33 clocks
+const PTS_WRAP: u64 = 1 << 33;
+
+
+/// Stream time by PCR, or by PTS if stream has no PCR.
+#[derive(Default)]
+struct Clock {
+    pcr_pid: Option<u16>,
+    pts_pid: Option<u16>,
+    last: Option<u64>,
+    /// Stream time since start in 27MHz clocks
+    elapsed: u64,
+}
+
+impl Clock {
+    /// Returns `true` if stream time is advanced by the packet.
+    fn check(&mut self, ts: &TsPacket) -> bool {
+        let pid = ts.get_pid();
+
+        if let Some(pcr) = ts.get_pcr() {
+            if self.pcr_pid.is_none() {
+                self.pcr_pid = Some(pid);
+                self.last = None;
+            }
+            if self.pcr_pid != Some(pid) {
+                return false
+            }
+
+            match self.last {
+                Some(last) if pcr_delta(last, pcr) <= MAX_TIME_DELTA => {
+                    self.elapsed += pcr_delta(last, pcr);
+                }
+                _ => {}
+            }
+            self.last = Some(pcr);
+            return true
+        }
+
+        if self.pcr_pid.is_some() || ! (ts.is_pusi() && ts.is_payload()) || ! ts.is_pes() {
+            return false
+        }
+
+        let pts = match PesPacket::new(ts.get_payload()).ok().and_then(|pes| pes.get_pts()) {
+            Some(v) => v,
+            None => return false,
+        };
+
+        if *self.pts_pid.get_or_insert(pid) != pid {
+            return false
+        }
+
+        let last = match self.last {
+            Some(v) => v,
+            None => {
+                self.last = Some(pts);
+                return false
+            }
+        };
+
+        let forward = (pts + PTS_WRAP - last) % PTS_WRAP;
+        let backward = (last + PTS_WRAP - pts) % PTS_WRAP;
+        if forward == 0 || backward * 300 <= MAX_TIME_DELTA {
+            // reordered frame
+            return false
+        }
+
+        if forward * 300 <= MAX_TIME_DELTA {
+            self.elapsed += forward * 300;
+        }
+        self.last = Some(pts);
+        true
+    }
+}
+
+
+/// Elementary stream of the program.
+#[derive(Debug, Serialize)]
+pub struct StreamInfo {
+    pub pid: u16,
+    pub stream_type: u8,
+    pub codec: &'static str,
+}
+
+
+/// Program from the PAT and PMT.
+#[derive(Debug, Serialize)]
+pub struct ProgramInfo {
+    pub pnr: u16,
+    pub pmt_pid: u16,
+    pub pcr_pid: Option<u16>,
+    /// service name from the SDT
+    pub name: Option<String>,
+    pub streams: Vec<StreamInfo>,
+}
+
+
+/// Counters of the single PID.
+#[derive(Debug, Serialize)]
+pub struct PidInfo {
+    pub pid: u16,
+    pub kind: String,
+    pub packets: u64,
+    pub scrambled: bool,
+    /// Average bitrate in bits per second
+    pub bitrate: u64,
+    /// Maximum bitrate in one second interval
+    pub peak_bitrate: u64,
+    pub cc_errors: u64,
+}
+
+
+/// Number of the errors by TR 101 290 check.
+#[derive(Debug, Serialize)]
+pub struct ErrorInfo {
+    pub check: String,
+    pub count: u64,
+}
+
+
+/// Result of the transport stream analysis.
+/// Formatted as text with `Display` or serialized to JSON.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    path: String,
+    packets: u64,
+    /// Duration in seconds by PCR or PTS
+    duration: f64,
+    /// Average bitrate in bits per second
+    bitrate: u64,
+    programs: Vec<ProgramInfo>,
+    pids: Vec<PidInfo>,
+    errors: Vec<ErrorInfo>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "File: {}", &self.path)?;
+        writeln!(f, "Packets: {}", self.packets)?;
+        writeln!(f, "Duration: {:.3} s", self.duration)?;
+        writeln!(f, "Bitrate: {} bit/s", self.bitrate)?;
+
+        for program in &self.programs {
+            writeln!(f)?;
+            write!(f, "Program {}", program.pnr)?;
+            if let Some(name) = &program.name {
+                write!(f, " \"{}\"", name)?;
+            }
+            write!(f, ": PMT PID {}", program.pmt_pid)?;
+            if let Some(pcr_pid) = program.pcr_pid {
+                write!(f, ", PCR PID {}", pcr_pid)?;
+            }
+            writeln!(f)?;
+
+            for stream in &program.streams {
+                writeln!(
+                    f,
+                    "    PID {}: {} (stream_type {:#04x})",
+                    stream.pid,
+                    stream.codec,
+                    stream.stream_type,
+                )?;
+            }
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{:>6} {:>10} {:>12} {:>12} {:>10}  Type", "PID", "Packets", "Bitrate", "Peak", "CC errors")?;
+        for pid in &self.pids {
+            writeln!(
+                f,
+                "{:>6} {:>10} {:>12} {:>12} {:>10}  {}{}",
+                pid.pid,
+                pid.packets,
+                pid.bitrate,
+                pid.peak_bitrate,
+                pid.cc_errors,
+                &pid.kind,
+                if pid.scrambled { " (scrambled)" } else { "" },
+            )?;
+        }
+
+        if ! self.errors.is_empty() {
+            writeln!(f)?;
+            writeln!(f, "Errors:")?;
+            for error in &self.errors {
+                writeln!(f, "    {}: {}", &error.check, error.count)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+#[derive(Default)]
+struct PidState {
+    packets: u64,
+    scrambled: bool,
+    cc_errors: u64,
+    /// Packets in the current peak window
+    window: u64,
+    peak_bitrate: u64,
+}
+
+
+/// Offline analysis of the transport stream.
+struct Probe {
+    stats: Arc<Stats>,
+    cc: CcTracker,
+    monitor: Monitor,
+    start: Instant,
+
+    clock: Clock,
+    /// Packets since last clock update
+    pending: Vec<u8>,
+    window_start: u64,
+
+    packets: u64,
+    pids: BTreeMap<u16, PidState>,
+
+    pat: Psi,
+    pmt: HashMap<u16, Psi>,
+    sdt: Psi,
+    programs: BTreeMap<u16, ProgramInfo>,
+    names: HashMap<u16, String>,
+}
+
+impl Probe {
+    fn new() -> Self {
+        let stats = Arc::new(Stats::default());
+
+        Self {
+            cc: CcTracker::default(),
+            monitor: Monitor::new(stats.clone()),
+            stats,
+            start: Instant::now(),
+
+            clock: Clock::default(),
+            pending: Vec::new(),
+            window_start: 0,
+
+            packets: 0,
+            pids: BTreeMap::new(),
+
+            pat: Psi::default(),
+            pmt: HashMap::new(),
+            sdt: Psi::default(),
+            programs: BTreeMap::new(),
+            names: HashMap::new(),
+        }
+    }
+
+    #[inline]
+    fn bitrate(packets: u64, time: u64) -> u64 {
+        if time == 0 {
+            0
+        } else {
+            (u128::from(packets) * (TS_PACKET_SIZE as u128 * 8) * u128::from(PCR_CLOCK) / u128::from(time)) as u64
+        }
+    }
+
+    fn on_pat(&mut self, section: &[u8]) {
+        let pat = match Pat::parse(section) {
+            Some(v) => v,
+            None => return,
+        };
+
+        for item in pat.items.iter().filter(|item| item.pnr != 0) {
+            self.pmt.entry(item.pid).or_default();
+            self.programs.entry(item.pnr).or_insert_with(|| ProgramInfo {
+                pnr: item.pnr,
+                pmt_pid: item.pid,
+                pcr_pid: None,
+                name: None,
+                streams: Vec::new(),
+            });
+        }
+    }
+
+    fn on_pmt(&mut self, section: &[u8]) {
+        let pmt = match Pmt::parse(section) {
+            Some(v) => v,
+            None => return,
+        };
+
+        if let Some(program) = self.programs.get_mut(&pmt.header.table_id_extension) {
+            program.pcr_pid = if pmt.pcr_pid != NULL_PID { Some(pmt.pcr_pid) } else { None };
+            program.streams = pmt.items.iter()
+                .map(|item| StreamInfo {
+                    pid: item.pid,
+                    stream_type: item.stream_type,
+                    codec: item.get_codec(),
+                })
+                .collect();
+        }
+    }
+
+    fn on_sdt(&mut self, section: &[u8]) {
+        let sdt = match Sdt::parse(section) {
+            Some(v) if v.header.table_id == SDT_ACTUAL_TABLE_ID => v,
+            _ => return,
+        };
+
+        for item in &sdt.items {
+            if let Some(name) = item.get_name() {
+                self.names.insert(item.sid, name);
+            }
+        }
+    }
+
+    fn on_time(&mut self) {
+        let time = self.clock.elapsed;
+        self.monitor.push(&self.pending, self.start + Duration::from_nanos(time * 1000 / 27));
+        self.pending.clear();
+
+        let window = time - self.window_start;
+        if window >= PEAK_WINDOW {
+            for state in self.pids.values_mut() {
+                state.peak_bitrate = state.peak_bitrate.max(Self::bitrate(state.window, window));
+                state.window = 0;
+            }
+            self.window_start = time;
+        }
+    }
+
+    fn push(&mut self, ts: &TsPacket) {
+        let pid = ts.get_pid();
+
+        self.packets += 1;
+        let state = self.pids.entry(pid).or_default();
+        state.packets += 1;
+        state.window += 1;
+        state.scrambled |= ts.is_scrambled();
+        if self.cc.check(ts) == Continuity::Error {
+            state.cc_errors += 1;
+        }
+        self.pending.extend_from_slice(ts.as_bytes());
+
+        match pid {
+            PAT_PID => {
+                for section in self.pat.push(ts) {
+                    self.on_pat(&section);
+                }
+            }
+            SDT_PID => {
+                for section in self.sdt.push(ts) {
+                    self.on_sdt(&section);
+                }
+            }
+            _ => {
+                let sections = match self.pmt.get_mut(&pid) {
+                    Some(psi) => psi.push(ts),
+                    None => Vec::new(),
+                };
+                for section in sections {
+                    self.on_pmt(&section);
+                }
+            }
+        }
+
+        if self.clock.check(ts) {
+            self.on_time();
+        }
+    }
+
+    fn get_kind(&self, pid: u16) -> String {
+        let kind = match pid {
+            PAT_PID => "PAT",
+            CAT_PID => "CAT",
+            NIT_PID => "NIT",
+            SDT_PID => "SDT/BAT",
+            EIT_PID => "EIT",
+            TDT_PID => "TDT/TOT",
+            NULL_PID => "Null",
+            _ if self.pmt.contains_key(&pid) => "PMT",
+            _ => {
+                let stream = self.programs.values()
+                    .flat_map(|program| program.streams.iter())
+                    .find(|stream| stream.pid == pid);
+                match stream {
+                    Some(stream) => stream.codec,
+                    None => "Unknown",
+                }
+            }
+        };
+
+        kind.to_owned()
+    }
+
+    fn report(mut self, path: &str) -> Report {
+        if ! self.pending.is_empty() {
+            self.on_time();
+        }
+
+        let duration = self.clock.elapsed;
+
+        let pids = self.pids.iter()
+            .map(|(&pid, state)| PidInfo {
+                pid,
+                kind: self.get_kind(pid),
+                packets: state.packets,
+                scrambled: state.scrambled,
+                bitrate: Self::bitrate(state.packets, duration),
+                // stream is shorter than peak window
+                peak_bitrate: if state.peak_bitrate != 0 {
+                    state.peak_bitrate
+                } else {
+                    Self::bitrate(state.packets, duration)
+                },
+                cc_errors: state.cc_errors,
+            })
+            .collect();
+
+        let errors = self.stats.get_errors().iter()
+            .map(|(check, error)| ErrorInfo {
+                check: check.to_string(),
+                count: error.count,
+            })
+            .collect();
+
+        let mut programs: Vec<ProgramInfo> = self.programs.into_values().collect();
+        for program in programs.iter_mut() {
+            program.name = self.names.get(&program.pnr).cloned();
+        }
+
+        Report {
+            path: path.to_owned(),
+            packets: self.packets,
+            duration: duration as f64 / PCR_CLOCK as f64,
+            bitrate: Self::bitrate(self.packets, duration),
+            programs,
+            pids,
+            errors,
+        }
+    }
+}
+
+
+/// Reads transport stream from the file and returns analysis report.
+pub async fn probe(path: &str) -> Result<Report, Error> {
+    let endpoint = || format!("file://{}", path);
+    let mut file = File::open(path).await
+        .map_err(|source| Error::Open { endpoint: endpoint(), source })?;
+
+    let mut probe = Probe::new();
+    let mut buf = vec![0; 1024 * TS_PACKET_SIZE];
+    let mut size = 0;
+    let mut synced = true;
+
+    loop {
+        let offset = file.read(&mut buf[size ..]).await
+            .map_err(|source| Error::Read { endpoint: endpoint(), source })?;
+        if offset == 0 {
+            break
+        }
+        size += offset;
+
+        let mut packets = TsIter::resume(&buf[.. size], synced);
+        for ts in &mut packets {
+            probe.push(&ts);
+        }
+
+        synced = packets.is_synced();
+        let skip = size - packets.remainder().len();
+        buf.copy_within(skip .. size, 0);
+        size -= skip;
+    }
+
+    Ok(probe.report(path))
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        std::error::Error as _,
+
+        crate::{
+            error::Error,
+            ts::{
+                TsPacket,
+                TsPacketMut,
+                TS_PACKET_SIZE,
+            },
+            psi::{
+                Pat,
+                PatItem,
+                SectionHeader,
+                packetize,
+            },
+        },
+
+        super::Probe,
+    };
+
+
+    #[test]
+    fn probe() {
+        let pat = Pat {
+            header: SectionHeader { table_id: 0x00, table_id_extension: 1, .. Default::default() },
+            items: vec![ PatItem { pnr: 1, pid: 0x1000 } ],
+        };
+        // PMT of program 1: H.264 on PID 0x100
+        let pmt = SectionHeader { table_id: 0x02, table_id_extension: 1, .. Default::default() }
+            .build(&[0xE1, 0x00, 0xF0, 0x00, 0x1B, 0xE1, 0x00, 0xF0, 0x00]);
+
+        let mut data = Vec::new();
+        packetize(&pat.build(), 0, &mut 0, &mut data);
+        packetize(&pmt, 0x1000, &mut 0, &mut data);
+
+        // 2 seconds with PCR every 100ms
+        for i in 0 .. 21 {
+            let mut ts = vec![0x47, 0x01, 0x00, 0x30 | (i as u8 & 0x0F), 7, 0x10];
+            ts.resize(TS_PACKET_SIZE, 0xFF);
+            TsPacketMut::new(&mut ts).unwrap().set_pcr(Some(i * 2_700_000));
+            data.extend(ts);
+        }
+
+        let mut probe = Probe::new();
+        for packet in data.chunks(TS_PACKET_SIZE) {
+            probe.push(&TsPacket::new(packet).unwrap());
+        }
+        let report = probe.report("test");
+
+        assert_eq!(report.packets, 23);
+        assert!((report.duration - 2.0).abs() < 0.001);
+        assert_eq!(report.programs.len(), 1);
+        assert_eq!(report.programs[0].pcr_pid, Some(0x100));
+        assert_eq!(report.programs[0].streams[0].codec, "H.264");
+
+        let pid = report.pids.iter().find(|pid| pid.pid == 0x100).unwrap();
+        assert_eq!(pid.kind, "H.264");
+        assert_eq!(pid.packets, 21);
+        assert_eq!(pid.cc_errors, 0);
+        assert_eq!(pid.bitrate, 21 * 188 * 8 / 2);
+    }
+
+    #[tokio::test]
+    async fn open_error() {
+        let err = super::probe("/nonexistent/tsplay.ts").await.unwrap_err();
+        assert!(matches!(&err, Error::Open { endpoint, .. } if endpoint == "file:///nonexistent/tsplay.ts"));
+        assert!(err.source().is_some());
+    }
+}
@@ -0,0 +1,111 @@
+use {
+    std::time::Duration,
+
+    super::packetize,
+};
+
+
+struct Table {
+    pid: u16,
+    sections: Vec<Vec<u8>>,
+    interval: Duration,
+    /// Time of the next repetition
+    next: Duration,
+    cc: u8,
+}
+
+
+/// Periodic insertion of the PSI/SI tables.
+///
+/// Each table is sent on its own PID with given repetition interval.
+/// Time is defined by the caller, for example by the position of the packet in the constant bitrate
+/// output, so inserted packets do not depend on the wall clock and do not disturb the pacing.
+#[derive(Default)]
+pub struct Carousel {
+    tables: Vec<Table>,
+}
+
+impl Carousel {
+    /// Sets sections of the table on given PID.
+    /// New or changed table is sent on the next call of `push()`.
+    pub fn set(&mut self, pid: u16, sections: Vec<Vec<u8>>, interval: Duration) {
+        match self.tables.iter_mut().find(|table| table.pid == pid) {
+            Some(table) => {
+                table.interval = interval;
+                if table.sections != sections {
+                    table.sections = sections;
+                    table.next = Duration::ZERO;
+                }
+            }
+            None => self.tables.push(Table {
+                pid,
+                sections,
+                interval,
+                next: Duration::ZERO,
+                cc: 0,
+            }),
+        }
+    }
+
+    /// Keeps only tables with PIDs for which `f` returns `true`.
+    pub fn retain<F: FnMut(u16) -> bool>(&mut self, mut f: F) {
+        self.tables.retain(|table| f(table.pid));
+    }
+
+    /// Appends TS packets of the tables to be repeated at `time` to the `out`.
+    pub fn push(&mut self, time: Duration, out: &mut Vec<u8>) {
+        for table in self.tables.iter_mut().filter(|table| time >= table.next) {
+            table.next = time + table.interval;
+            for section in &table.sections {
+                packetize(section, table.pid, &mut table.cc, out);
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        std::time::Duration,
+
+        crate::ts::{
+            TsIter,
+            TS_PACKET_SIZE,
+        },
+
+        super::Carousel,
+    };
+
+
+    #[test]
+    fn intervals() {
+        let mut carousel = Carousel::default();
+        carousel.set(0x00, vec![vec![0x00; 16]], Duration::from_millis(100));
+        carousel.set(0x11, vec![vec![0x42; 16], vec![0x42; 200]], Duration::from_millis(1000));
+
+        let mut pids = Vec::new();
+        for ms in (0 .. 2000).step_by(50) {
+            if ms == 1200 {
+                // changed table is sent immediately
+                carousel.set(0x11, vec![vec![0x42; 32]], Duration::from_millis(1000));
+            }
+
+            let mut out = Vec::new();
+            carousel.push(Duration::from_millis(ms), &mut out);
+            assert_eq!(out.len() % TS_PACKET_SIZE, 0);
+            pids.extend(TsIter::new(&out).map(|ts| (ms, ts.get_pid())));
+        }
+
+        let count = |pid| pids.iter().filter(|item| item.1 == pid).count();
+        assert_eq!(count(0x00), 20);
+        assert_eq!(count(0x11), 3 + 3 + 1);
+        assert_eq!(pids[.. 5], [(0, 0x00), (0, 0x11), (0, 0x11), (0, 0x11), (100, 0x00)]);
+        assert!(pids.contains(&(1000, 0x11)) && pids.contains(&(1200, 0x11)));
+
+        carousel.retain(|pid| pid != 0x11);
+        let mut out = Vec::new();
+        carousel.push(Duration::from_secs(10), &mut out);
+        assert_eq!(out.len(), TS_PACKET_SIZE);
+    }
+}
@@ -0,0 +1,60 @@
+const CRC32_POLY: u32 = 0x04C1_1DB7;
+
+
+const fn crc32_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u32) << 24;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if (crc & 0x8000_0000) != 0 {
+                (crc << 1) ^ CRC32_POLY
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+
+const CRC32_TABLE: [u32; 256] = crc32_table();
+
+
+/// CRC32/MPEG-2 checksum
+///
+/// ISO/IEC 13818-1
+///
+/// This is a 32-bit field that contains the CRC value that gives a zero output of the registers in the decoder
+/// defined in Annex A after processing the entire section.
+pub fn crc32(data: &[u8]) -> u32 {
+    data.iter().fold(0xFFFF_FFFF, |crc, &byte| {
+        (crc << 8) ^ CRC32_TABLE[(((crc >> 24) as u8) ^ byte) as usize]
+    })
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::crc32;
+
+
+    #[test]
+    fn check() {
+        assert_eq!(crc32(b"123456789"), 0x0376_E6E7);
+    }
+
+    #[test]
+    fn section() {
+        // PAT with single program 1 on PMT PID 0x1000
+        let pat: &[u8] = &[
+            0x00, 0xB0, 0x0D, 0x00, 0x01, 0xC1, 0x00, 0x00, 0x00, 0x01, 0xF0, 0x00,
+            0x2A, 0xB1, 0x04, 0xB2,
+        ];
+        assert_eq!(crc32(pat), 0);
+    }
+}
@@ -0,0 +1,115 @@
+/// Iterator over the descriptors loop. Yields descriptor tag and data.
+pub struct Descriptors<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Iterator for Descriptors<'a> {
+    type Item = (u8, &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let tag = *self.data.first()?;
+        let size = usize::from(*self.data.get(1)?);
+
+        let item = match self.data.get(2 .. 2 + size) {
+            Some(v) => (tag, v),
+            None => {
+                self.data = &[];
+                return None
+            }
+        };
+
+        self.data = &self.data[2 + size ..];
+        Some(item)
+    }
+}
+
+
+/// Returns iterator over the descriptors loop.
+#[inline]
+pub fn descriptors(data: &[u8]) -> Descriptors<'_> {
+    Descriptors { data }
+}
+
+
+/// CA_descriptor tag
+pub const CA_DESCRIPTOR: u8 = 0x09;
+/// network_name_descriptor tag
+pub const NETWORK_NAME_DESCRIPTOR: u8 = 0x40;
+/// service_list_descriptor tag
+pub const SERVICE_LIST_DESCRIPTOR: u8 = 0x41;
+/// service_descriptor tag
+pub const SERVICE_DESCRIPTOR: u8 = 0x48;
+
+
+/// Returns CA_PID of the CA_descriptor.
+///
+/// ISO/IEC 13818-1
+///
+/// The CA_PID is a 13-bit field indicating the PID of the Transport Stream packets which shall contain either
+/// ECM or EMM information for the CA systems as specified with the associated CA_system_ID.
+#[inline]
+pub fn get_ca_pid(data: &[u8]) -> Option<u16> {
+    let pid = data.get(2 .. 4)?;
+    Some((u16::from(pid[0] & 0x1F) << 8) | u16::from(pid[1]))
+}
+
+
+/// Returns descriptors loop with CA_PID of the CA_descriptors replaced with the result of `map`.
+/// CA_descriptor is removed if `map` returns `None`.
+pub fn map_ca_pids<F>(data: &[u8], mut map: F) -> Vec<u8>
+where
+    F: FnMut(u16) -> Option<u16>,
+{
+    let mut result = Vec::with_capacity(data.len());
+
+    for (tag, data) in descriptors(data) {
+        let start = result.len();
+        result.push(tag);
+        result.push(data.len() as u8);
+        result.extend_from_slice(data);
+
+        if tag != CA_DESCRIPTOR {
+            continue
+        }
+
+        match get_ca_pid(data).map(&mut map) {
+            Some(Some(pid)) => {
+                let field = &mut result[start + 4 .. start + 6];
+                field[0] = (field[0] & 0xE0) | (pid >> 8) as u8;
+                field[1] = pid as u8;
+            }
+            Some(None) => result.truncate(start),
+            None => {}
+        }
+    }
+
+    result
+}
+
+
+/// Decodes DVB text string. Character table selection bytes are skipped.
+///
+/// ETSI EN 300 468 Annex A
+pub fn decode_text(data: &[u8]) -> String {
+    let data = match data.first() {
+        Some(0x10) => data.get(3 ..).unwrap_or(&[]),
+        Some(0x1F) => data.get(2 ..).unwrap_or(&[]),
+        Some(&byte) if byte < 0x20 => &data[1 ..],
+        _ => data,
+    };
+
+    String::from_utf8_lossy(data).into_owned()
+}
+
+
+/// Encodes DVB text string. Non-ASCII text is encoded in UTF-8 with the character table selection byte.
+///
+/// ETSI EN 300 468 Annex A
+pub fn encode_text(text: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(text.len() + 1);
+    if ! text.is_ascii() {
+        data.push(0x15);
+    }
+    data.extend_from_slice(text.as_bytes());
+    data
+}
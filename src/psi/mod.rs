@@ -0,0 +1,78 @@
+mod crc32;
+pub use crc32::crc32;
+
+mod section;
+pub use section::{
+    Psi,
+    SectionHeader,
+    PSI_MAX_SIZE,
+    packetize,
+    is_valid_section,
+};
+
+mod descriptor;
+pub use descriptor::{
+    descriptors,
+    decode_text,
+    encode_text,
+    get_ca_pid,
+    map_ca_pids,
+    CA_DESCRIPTOR,
+    NETWORK_NAME_DESCRIPTOR,
+    SERVICE_LIST_DESCRIPTOR,
+    SERVICE_DESCRIPTOR,
+};
+
+mod pat;
+pub use pat::{
+    Pat,
+    PatItem,
+    PAT_TABLE_ID,
+};
+
+mod pmt;
+pub use pmt::{
+    Pmt,
+    PMT_TABLE_ID,
+};
+
+mod sdt;
+pub use sdt::{
+    Sdt,
+    SdtItem,
+    SDT_ACTUAL_TABLE_ID,
+};
+
+mod nit;
+pub use nit::{
+    Nit,
+    NitItem,
+    NIT_ACTUAL_TABLE_ID,
+};
+
+mod carousel;
+pub use carousel::Carousel;
+
+
+/// Program Association Table PID
+pub const PAT_PID: u16 = 0x0000;
+/// Conditional Access Table PID
+pub const CAT_PID: u16 = 0x0001;
+/// table_id of the conditional_access_section
+pub const CAT_TABLE_ID: u8 = 0x01;
+/// Network Information Table PID
+pub const NIT_PID: u16 = 0x0010;
+/// Service Description Table PID
+pub const SDT_PID: u16 = 0x0011;
+/// Event Information Table PID
+pub const EIT_PID: u16 = 0x0012;
+/// Time and Date Table and Time Offset Table PID
+pub const TDT_PID: u16 = 0x0014;
+
+
+/// Returns `true` if table_id is an EIT for actual transport stream:
+/// present/following (0x4E) or schedule (0x50 - 0x5F).
+#[inline]
+pub fn is_eit_actual(table_id: u8) -> bool {
+    table_id == 0x4E || (0x50 ..= 0x5F).contains(&table_id)
+}
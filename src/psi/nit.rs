@@ -0,0 +1,147 @@
+use super::SectionHeader;
+#[cfg(test)]
+use super::{
+    is_valid_section,
+    descriptors,
+    decode_text,
+    NETWORK_NAME_DESCRIPTOR,
+};
+
+
+/// table_id of the network_information_section for actual network
+pub const NIT_ACTUAL_TABLE_ID: u8 = 0x40;
+/// table_id of the network_information_section for other network
+#[cfg(test)]
+pub const NIT_OTHER_TABLE_ID: u8 = 0x41;
+
+
+/// Network Information Table transport stream item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NitItem {
+    /// transport_stream_id
+    pub tsid: u16,
+    /// original_network_id
+    pub onid: u16,
+    pub descriptors: Vec<u8>,
+}
+
+
+/// Network Information Table
+///
+/// ETSI EN 300 468
+///
+/// The NIT conveys information relating to the physical organization of the multiplexes/TSs carried via a given
+/// network, and the characteristics of the network itself. The table_id_extension contains network_id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Nit {
+    pub header: SectionHeader,
+    /// network descriptors
+    pub descriptors: Vec<u8>,
+    pub items: Vec<NitItem>,
+}
+
+impl Nit {
+    /// Parses NIT section. Returns `None` if section is not valid.
+    /// NIT is only built by the multiplexer, parser is used to check it.
+    #[cfg(test)]
+    pub fn parse(section: &[u8]) -> Option<Self> {
+        if ! is_valid_section(section) {
+            return None
+        }
+
+        if section[0] != NIT_ACTUAL_TABLE_ID && section[0] != NIT_OTHER_TABLE_ID {
+            return None
+        }
+
+        let header = SectionHeader::parse(section);
+        let data = &section[.. section.len() - 4];
+
+        let length = (usize::from(data.get(8)? & 0x0F) << 8) | usize::from(*data.get(9)?);
+        let descriptors = data.get(10 .. 10 + length)?.to_vec();
+
+        let mut items = Vec::new();
+        // skip transport_stream_loop_length
+        let mut skip = 10 + length + 2;
+        while skip < data.len() {
+            let item = data.get(skip .. skip + 6)?;
+            let length = (usize::from(item[4] & 0x0F) << 8) | usize::from(item[5]);
+            items.push(NitItem {
+                tsid: (u16::from(item[0]) << 8) | u16::from(item[1]),
+                onid: (u16::from(item[2]) << 8) | u16::from(item[3]),
+                descriptors: data.get(skip + 6 .. skip + 6 + length)?.to_vec(),
+            });
+            skip += 6 + length;
+        }
+
+        Some(Self { header, descriptors, items })
+    }
+
+    /// Returns network_name from the network_name_descriptor.
+    #[cfg(test)]
+    pub fn get_name(&self) -> Option<String> {
+        descriptors(&self.descriptors)
+            .find(|(tag, _)| *tag == NETWORK_NAME_DESCRIPTOR)
+            .map(|(_, data)| decode_text(data))
+    }
+
+    /// Builds NIT section.
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(0xF000 | self.descriptors.len() as u16).to_be_bytes());
+        data.extend_from_slice(&self.descriptors);
+
+        let length: usize = self.items.iter().map(|item| 6 + item.descriptors.len()).sum();
+        data.extend_from_slice(&(0xF000 | length as u16).to_be_bytes());
+
+        for item in &self.items {
+            data.extend_from_slice(&item.tsid.to_be_bytes());
+            data.extend_from_slice(&item.onid.to_be_bytes());
+            data.extend_from_slice(&(0xF000 | item.descriptors.len() as u16).to_be_bytes());
+            data.extend_from_slice(&item.descriptors);
+        }
+
+        self.header.build(&data)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Nit,
+        NitItem,
+        SectionHeader,
+        NIT_ACTUAL_TABLE_ID,
+    };
+
+
+    #[test]
+    fn build_parse() {
+        let mut descriptors = vec![0x40, 0x07];
+        descriptors.extend_from_slice(b"Network");
+
+        let nit = Nit {
+            header: SectionHeader {
+                table_id: NIT_ACTUAL_TABLE_ID,
+                table_id_extension: 1,
+                version: 2,
+                .. Default::default()
+            },
+            descriptors,
+            items: vec![
+                NitItem {
+                    tsid: 1,
+                    onid: 1,
+                    // service_list_descriptor
+                    descriptors: vec![0x41, 0x06, 0x00, 0x64, 0x01, 0x00, 0x65, 0x02],
+                },
+                NitItem { tsid: 2, onid: 1, descriptors: vec![] },
+            ],
+        };
+
+        let section = nit.build();
+        let parsed = Nit::parse(&section).unwrap();
+        assert_eq!(parsed, nit);
+        assert_eq!(parsed.get_name().as_deref(), Some("Network"));
+    }
+}
@@ -0,0 +1,63 @@
+use super::{
+    SectionHeader,
+    is_valid_section,
+};
+
+
+/// table_id of the program_association_section
+pub const PAT_TABLE_ID: u8 = 0x00;
+
+
+/// Program Association Table item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatItem {
+    /// program_number. Value 0 is reserved for the network_PID
+    pub pnr: u16,
+    /// program_map_PID or network_PID
+    pub pid: u16,
+}
+
+
+/// Program Association Table
+///
+/// ISO/IEC 13818-1
+///
+/// The Program Association Table provides the correspondence between a program_number and the PID value of the
+/// Transport Stream packets which carry the program definition. The program_number is the numeric label
+/// associated with a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pat {
+    pub header: SectionHeader,
+    pub items: Vec<PatItem>,
+}
+
+impl Pat {
+    /// Parses PAT section. Returns `None` if section is not valid.
+    pub fn parse(section: &[u8]) -> Option<Self> {
+        if ! is_valid_section(section) || section[0] != PAT_TABLE_ID {
+            return None
+        }
+
+        let header = SectionHeader::parse(section);
+        let items = section[8 .. section.len() - 4]
+            .chunks_exact(4)
+            .map(|item| PatItem {
+                pnr: (u16::from(item[0]) << 8) | u16::from(item[1]),
+                pid: (u16::from(item[2] & 0x1F) << 8) | u16::from(item[3]),
+            })
+            .collect();
+
+        Some(Self { header, items })
+    }
+
+    /// Builds PAT section.
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.items.len() * 4);
+        for item in &self.items {
+            data.extend_from_slice(&item.pnr.to_be_bytes());
+            data.extend_from_slice(&(0xE000 | item.pid).to_be_bytes());
+        }
+
+        self.header.build(&data)
+    }
+}
@@ -0,0 +1,162 @@
+use super::{
+    SectionHeader,
+    is_valid_section,
+    descriptors,
+};
+
+
+/// table_id of the TS_program_map_section
+pub const PMT_TABLE_ID: u8 = 0x02;
+
+
+/// Program Map Table elementary stream item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PmtItem {
+    pub stream_type: u8,
+    /// elementary_PID
+    pub pid: u16,
+    /// ES_info descriptors
+    pub descriptors: Vec<u8>,
+}
+
+impl PmtItem {
+    /// Returns codec name by stream_type and ES_info descriptors.
+    pub fn get_codec(&self) -> &'static str {
+        match self.stream_type {
+            0x01 => "MPEG-1 Video",
+            0x02 => "MPEG-2 Video",
+            0x03 => "MPEG-1 Audio",
+            0x04 => "MPEG-2 Audio",
+            0x05 => "Private Sections",
+            0x0F => "AAC",
+            0x10 => "MPEG-4 Video",
+            0x11 => "AAC LATM",
+            0x15 => "Metadata",
+            0x1B => "H.264",
+            0x24 => "HEVC",
+            0x81 => "AC-3",
+            0x87 => "E-AC-3",
+            0x06 => {
+                // DVB private data is identified by descriptor. ETSI EN 300 468
+                let tag = descriptors(&self.descriptors)
+                    .map(|(tag, _)| tag)
+                    .find(|tag| matches!(tag, 0x56 | 0x59 | 0x6A | 0x7A | 0x7B | 0x7F));
+                match tag {
+                    Some(0x56) => "Teletext",
+                    Some(0x59) => "DVB Subtitles",
+                    Some(0x6A) => "AC-3",
+                    Some(0x7A) => "E-AC-3",
+                    Some(0x7B) => "DTS",
+                    Some(0x7F) => "Extension",
+                    _ => "Private Data",
+                }
+            }
+            _ => "Unknown",
+        }
+    }
+}
+
+
+/// Program Map Table
+///
+/// ISO/IEC 13818-1
+///
+/// The Program Map Table provides the mappings between program numbers and the program elements that comprise
+/// them. A single instance of such a mapping is referred to as a "program definition".
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pmt {
+    pub header: SectionHeader,
+    pub pcr_pid: u16,
+    /// program_info descriptors
+    pub descriptors: Vec<u8>,
+    pub items: Vec<PmtItem>,
+}
+
+impl Pmt {
+    /// Parses PMT section. Returns `None` if section is not valid.
+    pub fn parse(section: &[u8]) -> Option<Self> {
+        if ! is_valid_section(section) || section[0] != PMT_TABLE_ID {
+            return None
+        }
+
+        let header = SectionHeader::parse(section);
+        let data = &section[.. section.len() - 4];
+
+        let pcr_pid = (u16::from(data.get(8)? & 0x1F) << 8) | u16::from(*data.get(9)?);
+        let info_length = (usize::from(data.get(10)? & 0x0F) << 8) | usize::from(*data.get(11)?);
+        let descriptors = data.get(12 .. 12 + info_length)?.to_vec();
+
+        let mut items = Vec::new();
+        let mut skip = 12 + info_length;
+        while skip < data.len() {
+            let item = data.get(skip .. skip + 5)?;
+            let es_info_length = (usize::from(item[3] & 0x0F) << 8) | usize::from(item[4]);
+            items.push(PmtItem {
+                stream_type: item[0],
+                pid: (u16::from(item[1] & 0x1F) << 8) | u16::from(item[2]),
+                descriptors: data.get(skip + 5 .. skip + 5 + es_info_length)?.to_vec(),
+            });
+            skip += 5 + es_info_length;
+        }
+
+        Some(Self { header, pcr_pid, descriptors, items })
+    }
+
+    /// Builds PMT section.
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&(0xE000 | self.pcr_pid).to_be_bytes());
+        data.extend_from_slice(&(0xF000 | self.descriptors.len() as u16).to_be_bytes());
+        data.extend_from_slice(&self.descriptors);
+
+        for item in &self.items {
+            data.push(item.stream_type);
+            data.extend_from_slice(&(0xE000 | item.pid).to_be_bytes());
+            data.extend_from_slice(&(0xF000 | item.descriptors.len() as u16).to_be_bytes());
+            data.extend_from_slice(&item.descriptors);
+        }
+
+        self.header.build(&data)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Pmt,
+        PmtItem,
+        SectionHeader,
+    };
+
+
+    #[test]
+    fn build_parse() {
+        let pmt = Pmt {
+            header: SectionHeader {
+                table_id: 0x02,
+                table_id_extension: 1,
+                version: 3,
+                .. Default::default()
+            },
+            pcr_pid: 0x100,
+            descriptors: vec![],
+            items: vec![
+                PmtItem { stream_type: 0x1B, pid: 0x100, descriptors: vec![] },
+                PmtItem { stream_type: 0x03, pid: 0x101, descriptors: vec![0x0A, 0x04, b'e', b'n', b'g', 0x00] },
+            ],
+        };
+
+        let section = pmt.build();
+        assert_eq!(Pmt::parse(&section), Some(pmt));
+    }
+
+    #[test]
+    fn get_codec() {
+        let item = PmtItem { stream_type: 0x06, pid: 0x102, descriptors: vec![0x0A, 0x00, 0x6A, 0x01, 0x00] };
+        assert_eq!(item.get_codec(), "AC-3");
+
+        let item = PmtItem { stream_type: 0x1B, pid: 0x100, descriptors: vec![] };
+        assert_eq!(item.get_codec(), "H.264");
+    }
+}
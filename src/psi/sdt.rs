@@ -0,0 +1,161 @@
+use super::{
+    SectionHeader,
+    is_valid_section,
+    descriptors,
+    decode_text,
+    SERVICE_DESCRIPTOR,
+};
+
+
+/// table_id of the service_description_section for actual transport stream
+pub const SDT_ACTUAL_TABLE_ID: u8 = 0x42;
+/// table_id of the service_description_section for other transport stream
+pub const SDT_OTHER_TABLE_ID: u8 = 0x46;
+
+
+/// Service Description Table item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SdtItem {
+    /// service_id. Same as program_number in the PMT
+    pub sid: u16,
+    pub eit_schedule: bool,
+    pub eit_present_following: bool,
+    pub running_status: u8,
+    pub free_ca: bool,
+    pub descriptors: Vec<u8>,
+}
+
+impl SdtItem {
+    /// Returns service_name from the service_descriptor.
+    pub fn get_name(&self) -> Option<String> {
+        let (_, data) = descriptors(&self.descriptors)
+            .find(|(tag, _)| *tag == SERVICE_DESCRIPTOR)?;
+
+        let provider_length = usize::from(*data.get(1)?);
+        let skip = 2 + provider_length;
+        let name_length = usize::from(*data.get(skip)?);
+        let name = data.get(skip + 1 .. skip + 1 + name_length)?;
+
+        Some(decode_text(name))
+    }
+}
+
+
+/// Service Description Table
+///
+/// ETSI EN 300 468
+///
+/// Each sub_table of the SDT shall describe services that are contained within a particular transport stream.
+/// The services may be part of the actual transport stream or part of other transport streams, these being
+/// identified by means of the table_id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sdt {
+    pub header: SectionHeader,
+    pub onid: u16,
+    pub items: Vec<SdtItem>,
+}
+
+impl Sdt {
+    /// Parses SDT section. Returns `None` if section is not valid.
+    pub fn parse(section: &[u8]) -> Option<Self> {
+        if ! is_valid_section(section) {
+            return None
+        }
+
+        if section[0] != SDT_ACTUAL_TABLE_ID && section[0] != SDT_OTHER_TABLE_ID {
+            return None
+        }
+
+        let header = SectionHeader::parse(section);
+        let data = &section[.. section.len() - 4];
+
+        let onid = (u16::from(*data.get(8)?) << 8) | u16::from(*data.get(9)?);
+
+        let mut items = Vec::new();
+        let mut skip = 11;
+        while skip < data.len() {
+            let item = data.get(skip .. skip + 5)?;
+            let length = (usize::from(item[3] & 0x0F) << 8) | usize::from(item[4]);
+            items.push(SdtItem {
+                sid: (u16::from(item[0]) << 8) | u16::from(item[1]),
+                eit_schedule: (item[2] & 0x02) != 0,
+                eit_present_following: (item[2] & 0x01) != 0,
+                running_status: item[3] >> 5,
+                free_ca: (item[3] & 0x10) != 0,
+                descriptors: data.get(skip + 5 .. skip + 5 + length)?.to_vec(),
+            });
+            skip += 5 + length;
+        }
+
+        Some(Self { header, onid, items })
+    }
+
+    /// Builds SDT section.
+    pub fn build(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&self.onid.to_be_bytes());
+        data.push(0xFF);
+
+        for item in &self.items {
+            data.extend_from_slice(&item.sid.to_be_bytes());
+            data.push(
+                0xFC |
+                (u8::from(item.eit_schedule) << 1) |
+                u8::from(item.eit_present_following)
+            );
+            let length = item.descriptors.len() as u16;
+            data.extend_from_slice(&(
+                (u16::from(item.running_status & 0x07) << 13) |
+                (u16::from(item.free_ca) << 12) |
+                (length & 0x0FFF)
+            ).to_be_bytes());
+            data.extend_from_slice(&item.descriptors);
+        }
+
+        self.header.build(&data)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::{
+        Sdt,
+        SdtItem,
+        SectionHeader,
+        SDT_ACTUAL_TABLE_ID,
+    };
+
+
+    #[test]
+    fn build_parse() {
+        let mut descriptors = vec![0x48, 0x0C, 0x01, 0x03];
+        descriptors.extend_from_slice(b"CTV");
+        descriptors.push(0x06);
+        descriptors.extend_from_slice(b"Sample");
+
+        let sdt = Sdt {
+            header: SectionHeader {
+                table_id: SDT_ACTUAL_TABLE_ID,
+                table_id_extension: 1,
+                .. Default::default()
+            },
+            onid: 1,
+            items: vec![
+                SdtItem {
+                    sid: 100,
+                    eit_schedule: false,
+                    eit_present_following: true,
+                    running_status: 4,
+                    free_ca: false,
+                    descriptors,
+                },
+            ],
+        };
+
+        let section = sdt.build();
+        let parsed = Sdt::parse(&section).unwrap();
+        assert_eq!(parsed, sdt);
+        assert_eq!(parsed.items[0].get_name().as_deref(), Some("Sample"));
+    }
+}
@@ -0,0 +1,276 @@
+use {
+    crate::ts::{
+        TsPacket,
+        TsPacketMut,
+        TS_PACKET_SIZE,
+    },
+
+    super::crc32,
+};
+
+
+/// Maximum size of the PSI section including 3 bytes of the header.
+pub const PSI_MAX_SIZE: usize = 1024;
+/// Maximum size of the private section including 3 bytes of the header.
+/// DVB SI tables, like SDT and EIT, are private sections.
+pub const PRIVATE_SECTION_MAX_SIZE: usize = 4096;
+
+
+/// Returns size of the section with header, or `None` if buffer is too short.
+#[inline]
+fn section_size(section: &[u8]) -> Option<usize> {
+    let length = section.get(1 .. 3)?;
+    Some(3 + ((usize::from(length[0] & 0x0F) << 8) | usize::from(length[1])))
+}
+
+
+/// Checks that section has complete long header and valid CRC32.
+pub fn is_valid_section(section: &[u8]) -> bool {
+    match section_size(section) {
+        Some(size) => size >= 12 && size == section.len() && crc32(section) == 0,
+        None => false,
+    }
+}
+
+
+/// Fields of the long section header.
+///
+/// ISO/IEC 13818-1
+///
+/// Common fields of the sections with section_syntax_indicator set to '1'. The table_id_extension contains
+/// transport_stream_id for PAT, program_number for PMT, and service_id for EIT.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct SectionHeader {
+    pub table_id: u8,
+    pub table_id_extension: u16,
+    pub version: u8,
+    pub section_number: u8,
+    pub last_section_number: u8,
+}
+
+impl SectionHeader {
+    /// Parses header of the section. Section should be checked with `is_valid_section()` before.
+    pub fn parse(section: &[u8]) -> Self {
+        Self {
+            table_id: section[0],
+            table_id_extension: (u16::from(section[3]) << 8) | u16::from(section[4]),
+            version: (section[5] & 0x3E) >> 1,
+            section_number: section[6],
+            last_section_number: section[7],
+        }
+    }
+
+    /// Builds complete section with header, `data` and CRC32.
+    pub fn build(&self, data: &[u8]) -> Vec<u8> {
+        // 5 bytes of the header after section_length and 4 bytes of CRC32
+        let length = 5 + data.len() + 4;
+
+        let mut section = Vec::with_capacity(3 + length);
+        section.push(self.table_id);
+        section.push(0xB0 | ((length >> 8) as u8 & 0x0F));
+        section.push(length as u8);
+        section.push((self.table_id_extension >> 8) as u8);
+        section.push(self.table_id_extension as u8);
+        section.push(0xC1 | ((self.version & 0x1F) << 1));
+        section.push(self.section_number);
+        section.push(self.last_section_number);
+        section.extend_from_slice(data);
+
+        let crc = crc32(&section);
+        section.extend_from_slice(&crc.to_be_bytes());
+
+        section
+    }
+}
+
+
+/// Assembles PSI sections from the payload of the TS packets with the same PID.
+/// Sections up to the `PRIVATE_SECTION_MAX_SIZE` are accepted.
+#[derive(Default)]
+pub struct Psi {
+    buffer: Vec<u8>,
+    cc: Option<u8>,
+}
+
+impl Psi {
+    fn take_sections(&mut self, sections: &mut Vec<Vec<u8>>) {
+        while let Some(size) = section_size(&self.buffer) {
+            if self.buffer[0] == 0xFF || size > PRIVATE_SECTION_MAX_SIZE {
+                // stuffing bytes till the end of packet, or broken section
+                self.buffer.clear();
+                return
+            }
+
+            if self.buffer.len() < size {
+                return
+            }
+
+            sections.push(self.buffer.drain(.. size).collect());
+        }
+    }
+
+    /// Appends TS packet payload. Returns list of the completed sections.
+    pub fn push(&mut self, ts: &TsPacket) -> Vec<Vec<u8>> {
+        let mut sections = Vec::new();
+
+        if ts.is_error() || ! ts.is_payload() {
+            return sections
+        }
+
+        let cc = ts.get_cc();
+        if let Some(last) = self.cc {
+            if cc == last {
+                // duplicate packet
+                return sections
+            }
+
+            if cc != ((last + 1) & 0x0F) {
+                self.buffer.clear();
+            }
+        }
+        self.cc = Some(cc);
+
+        let payload = ts.get_payload();
+
+        if ts.is_pusi() {
+            let pointer = match payload.first() {
+                Some(&pointer) => usize::from(pointer),
+                None => return sections,
+            };
+
+            let tail = match payload.get(1 .. 1 + pointer) {
+                Some(tail) => tail,
+                None => {
+                    self.buffer.clear();
+                    return sections
+                }
+            };
+
+            if ! self.buffer.is_empty() {
+                self.buffer.extend_from_slice(tail);
+                self.take_sections(&mut sections);
+                self.buffer.clear();
+            }
+
+            self.buffer.extend_from_slice(&payload[1 + pointer ..]);
+        } else {
+            if self.buffer.is_empty() {
+                return sections
+            }
+
+            self.buffer.extend_from_slice(payload);
+        }
+
+        self.take_sections(&mut sections);
+        sections
+    }
+}
+
+
+/// Splits section into TS packets with given PID. Continuity counter is incremented for each packet.
+pub fn packetize(section: &[u8], pid: u16, cc: &mut u8, out: &mut Vec<u8>) {
+    let mut data = section;
+    let mut pusi = true;
+    let mut payload = Vec::with_capacity(TS_PACKET_SIZE - 4);
+
+    while pusi || ! data.is_empty() {
+        let mut packet = TsPacketMut::push(out);
+        packet.set_pid(pid);
+        packet.set_cc(*cc);
+        packet.set_pusi(pusi);
+        *cc = (*cc + 1) & 0x0F;
+
+        payload.clear();
+        if pusi {
+            // pointer_field
+            payload.push(0x00);
+            pusi = false;
+        }
+
+        let size = (TS_PACKET_SIZE - 4 - payload.len()).min(data.len());
+        payload.extend_from_slice(&data[.. size]);
+        data = &data[size ..];
+
+        // section is followed by stuffing bytes up to the end of the packet
+        payload.resize(TS_PACKET_SIZE - 4, 0xFF);
+        packet.set_payload(&payload);
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::ts::{
+            TsPacket,
+            TS_PACKET_SIZE,
+        },
+
+        super::{
+            Psi,
+            SectionHeader,
+            packetize,
+            is_valid_section,
+        },
+    };
+
+
+    const PAT: &[u8] = &[
+        0x00, 0xB0, 0x0D, 0x00, 0x01, 0xC1, 0x00, 0x00, 0x00, 0x01, 0xF0, 0x00,
+        0x2A, 0xB1, 0x04, 0xB2,
+    ];
+
+
+    #[test]
+    fn header() {
+        assert!(is_valid_section(PAT));
+
+        let header = SectionHeader::parse(PAT);
+        assert_eq!(header.table_id, 0x00);
+        assert_eq!(header.table_id_extension, 1);
+        assert_eq!(header.version, 0);
+
+        assert_eq!(header.build(&PAT[8 .. 12]), PAT);
+    }
+
+    #[test]
+    fn single_packet() {
+        let mut cc = 15;
+        let mut out = Vec::new();
+        packetize(PAT, 0, &mut cc, &mut out);
+        assert_eq!(out.len(), TS_PACKET_SIZE);
+        assert_eq!(cc, 0);
+
+        let ts = TsPacket::new(&out).unwrap();
+        assert!(ts.is_pusi());
+        assert_eq!(ts.get_pid(), 0);
+        assert_eq!(ts.get_cc(), 15);
+
+        let mut psi = Psi::default();
+        assert_eq!(psi.push(&ts), vec![PAT.to_vec()]);
+    }
+
+    #[test]
+    fn multiple_packets() {
+        let header = SectionHeader {
+            table_id: 0x02,
+            table_id_extension: 1,
+            .. Default::default()
+        };
+        let section = header.build(&[0x55; 400]);
+        assert!(is_valid_section(&section));
+
+        let mut cc = 0;
+        let mut out = Vec::new();
+        packetize(&section, 0x100, &mut cc, &mut out);
+        assert_eq!(out.len(), 3 * TS_PACKET_SIZE);
+
+        let mut psi = Psi::default();
+        let mut sections = Vec::new();
+        for chunk in out.chunks(TS_PACKET_SIZE) {
+            let ts = TsPacket::new(chunk).unwrap();
+            sections.extend(psi.push(&ts));
+        }
+        assert_eq!(sections, vec![section]);
+    }
+}
@@ -0,0 +1,380 @@
+use {
+    std::{
+        collections::{
+            BTreeMap,
+            VecDeque,
+        },
+        time::{
+            SystemTime,
+            UNIX_EPOCH,
+        },
+        sync::{
+            Mutex,
+            MutexGuard,
+            atomic::{
+                AtomicU64,
+                AtomicUsize,
+                AtomicBool,
+                Ordering,
+            },
+        },
+    },
+
+    serde_json::{
+        json,
+        Value,
+    },
+
+    crate::{
+        ts::{
+            TsIter,
+            TS_PACKET_SIZE,
+        },
+        monitor::Check,
+    },
+};
+
+
+/// Number of the seconds in the packet history. Longest bitrate window
+const HISTORY_SIZE: usize = 60;
+/// Bitrate windows in seconds
+const BITRATE_WINDOWS: [usize; 3] = [1, 10, 60];
+
+
+/// Locks counters. Counters are still usable if other thread panicked with the lock.
+#[inline]
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+
+/// Returns bitrate by windows in JSON: `{ "1s": ..., "10s": ..., "60s": ... }`
+fn bitrate_json<F: Fn(usize) -> u64>(bitrate: F) -> Value {
+    let map: serde_json::Map<String, Value> = BITRATE_WINDOWS.iter()
+        .map(|&seconds| (format!("{}s", seconds), json!(bitrate(seconds))))
+        .collect();
+    Value::Object(map)
+}
+
+
+/// Counters of the single PID.
+#[derive(Default, Debug, Clone)]
+pub struct PidStats {
+    /// Total number of the output packets
+    pub packets: u64,
+    /// Packets with transport_scrambling_control not equal to '00'
+    pub scrambled: u64,
+    /// Packets with transport_error_indicator
+    pub transport_errors: u64,
+    /// Packets with unexpected continuity_counter
+    pub cc_errors: u64,
+    /// Duplicate packets
+    pub cc_duplicates: u64,
+    /// continuity_counter discontinuities signalled with discontinuity_indicator
+    pub cc_discontinuities: u64,
+
+    /// Packets in the current second
+    current: u64,
+    /// Packets in the last seconds, latest first
+    history: VecDeque<u64>,
+}
+
+impl PidStats {
+    /// Creates counters of the PID appeared after `seconds` of the stream.
+    /// History is padded with zeros so all PIDs are averaged over the same window.
+    fn new(seconds: usize) -> Self {
+        Self {
+            history: VecDeque::from(vec![0; seconds]),
+            .. Self::default()
+        }
+    }
+
+    /// Returns average bitrate over the last `seconds` in bits per second.
+    /// Only complete seconds are counted.
+    pub fn bitrate(&self, seconds: usize) -> u64 {
+        let seconds = seconds.min(self.history.len());
+        if seconds == 0 {
+            return 0
+        }
+
+        let packets: u64 = self.history.iter().take(seconds).sum();
+        packets * (TS_PACKET_SIZE as u64 * 8) / seconds as u64
+    }
+
+    fn tick(&mut self) {
+        self.history.push_front(self.current);
+        self.history.truncate(HISTORY_SIZE);
+        self.current = 0;
+    }
+}
+
+
+/// Counter of the monitoring check.
+#[derive(Debug, Clone, Copy)]
+pub struct ErrorStats {
+    pub count: u64,
+    /// Time of the last error
+    pub last: SystemTime,
+}
+
+
+/// Counters of the stream. Shared between the stream task and the application.
+#[derive(Default)]
+pub struct Stats {
+    /// Total number of the input packets
+    pub input_packets: AtomicU64,
+    /// Total number of the input bytes
+    pub input_bytes: AtomicU64,
+    /// Number of the input reopens after error
+    pub input_reconnects: AtomicU64,
+    /// Number of the input restarts from the beginning
+    pub loops: AtomicU64,
+    /// Maximum PCR inaccuracy in nanoseconds measured in the last second
+    pub pcr_jitter: AtomicU64,
+    pcr_jitter_current: AtomicU64,
+    /// Total number of the output packets
+    pub packets: AtomicU64,
+    /// Output position in bytes
+    pub position: AtomicU64,
+    /// Null packets removed from the output
+    pub null_removed: AtomicU64,
+    /// Number of the failed writes to the output
+    pub output_errors: AtomicU64,
+    /// Stream task is waiting for the input data
+    pub waiting: AtomicBool,
+    /// Input and output of the stream are opened
+    pub opened: AtomicBool,
+    /// Number of the complete seconds in the bitrate history
+    seconds: AtomicUsize,
+    /// Counters by PID
+    pids: Mutex<BTreeMap<u16, PidStats>>,
+    /// Errors of the TR 101 290 checks
+    errors: Mutex<BTreeMap<Check, ErrorStats>>,
+}
+
+impl Stats {
+    /// Returns value which is changed on each step of the stream task.
+    #[inline]
+    pub fn progress(&self) -> u64 {
+        self.input_bytes.load(Ordering::Relaxed)
+            .wrapping_add(self.position.load(Ordering::Relaxed))
+    }
+
+    /// Counts output packets.
+    pub fn push(&self, data: &[u8]) {
+        let mut pids = lock(&self.pids);
+        let seconds = self.seconds.load(Ordering::Relaxed);
+        let mut packets = 0;
+
+        for ts in TsIter::new(data) {
+            let stats = pids.entry(ts.get_pid()).or_insert_with(|| PidStats::new(seconds));
+            stats.packets += 1;
+            stats.current += 1;
+            if ts.is_scrambled() {
+                stats.scrambled += 1;
+            }
+            if ts.is_error() {
+                stats.transport_errors += 1;
+            }
+            packets += 1;
+        }
+
+        self.packets.fetch_add(packets, Ordering::Relaxed);
+        self.position.fetch_add(data.len() as u64, Ordering::Relaxed);
+    }
+
+    /// Updates maximum PCR inaccuracy in nanoseconds.
+    #[inline]
+    pub fn update_pcr_jitter(&self, jitter: u64) {
+        self.pcr_jitter_current.fetch_max(jitter, Ordering::Relaxed);
+    }
+
+    /// Completes current second of the bitrate history. Should be called every second.
+    pub fn tick(&self) {
+        let jitter = self.pcr_jitter_current.swap(0, Ordering::Relaxed);
+        self.pcr_jitter.store(jitter, Ordering::Relaxed);
+
+        let mut pids = lock(&self.pids);
+        for stats in pids.values_mut() {
+            stats.tick();
+        }
+        let seconds = self.seconds.load(Ordering::Relaxed);
+        self.seconds.store((seconds + 1).min(HISTORY_SIZE), Ordering::Relaxed);
+    }
+
+    /// Returns average bitrate of all PIDs over the last `seconds` in bits per second.
+    pub fn bitrate(&self, seconds: usize) -> u64 {
+        let pids = lock(&self.pids);
+        pids.values().map(|stats| stats.bitrate(seconds)).sum()
+    }
+
+    /// Updates counters of the given PID.
+    pub fn update_pid<F: FnOnce(&mut PidStats)>(&self, pid: u16, f: F) {
+        let mut pids = lock(&self.pids);
+        let seconds = self.seconds.load(Ordering::Relaxed);
+        f(pids.entry(pid).or_insert_with(|| PidStats::new(seconds)));
+    }
+
+    /// Returns copy of the counters by PID.
+    pub fn get_pids(&self) -> BTreeMap<u16, PidStats> {
+        lock(&self.pids).clone()
+    }
+
+    /// Counts error of the monitoring check.
+    pub fn report_error(&self, check: Check) {
+        let mut errors = lock(&self.errors);
+        let now = SystemTime::now();
+        errors.entry(check)
+            .and_modify(|e| {
+                e.count += 1;
+                e.last = now;
+            })
+            .or_insert(ErrorStats { count: 1, last: now });
+    }
+
+    /// Returns copy of the monitoring errors.
+    pub fn get_errors(&self) -> BTreeMap<Check, ErrorStats> {
+        lock(&self.errors).clone()
+    }
+
+    /// Returns counters in JSON.
+    pub fn to_json(&self) -> Value {
+        let pids: serde_json::Map<String, Value> = self.get_pids().iter()
+            .map(|(pid, stats)| (pid.to_string(), json!({
+                "packets": stats.packets,
+                "bitrate": bitrate_json(|seconds| stats.bitrate(seconds)),
+                "scrambled": stats.scrambled,
+                "transport_errors": stats.transport_errors,
+                "cc_errors": stats.cc_errors,
+                "cc_duplicates": stats.cc_duplicates,
+                "cc_discontinuities": stats.cc_discontinuities,
+            })))
+            .collect();
+
+        let errors: serde_json::Map<String, Value> = self.get_errors().iter()
+            .map(|(check, error)| (check.to_string(), json!({
+                "count": error.count,
+                "last": error.last.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            })))
+            .collect();
+
+        json!({
+            "packets": self.packets.load(Ordering::Relaxed),
+            "position": self.position.load(Ordering::Relaxed),
+            "bitrate": bitrate_json(|seconds| self.bitrate(seconds)),
+            "null_removed": self.null_removed.load(Ordering::Relaxed),
+            "output_errors": self.output_errors.load(Ordering::Relaxed),
+            "pids": pids,
+            "errors": errors,
+        })
+    }
+
+    /// Returns human readable summary of the counters.
+    pub fn summary(&self) -> String {
+        let bitrate: Vec<String> = BITRATE_WINDOWS.iter()
+            .map(|&seconds| self.bitrate(seconds).to_string())
+            .collect();
+
+        let mut result = format!(
+            "packets: {}, position: {}, bitrate 1s/10s/60s: {}, null packets removed: {}",
+            self.packets.load(Ordering::Relaxed),
+            self.position.load(Ordering::Relaxed),
+            bitrate.join("/"),
+            self.null_removed.load(Ordering::Relaxed),
+        );
+
+        for (pid, stats) in self.get_pids() {
+            let bitrate: Vec<String> = BITRATE_WINDOWS.iter()
+                .map(|&seconds| stats.bitrate(seconds).to_string())
+                .collect();
+
+            result += &format!(
+                concat!(
+                    "\n    pid {}: packets: {}, bitrate 1s/10s/60s: {}, scrambled: {}, transport errors: {}, ",
+                    "cc errors: {}, duplicates: {}, discontinuities: {}",
+                ),
+                pid,
+                stats.packets,
+                bitrate.join("/"),
+                stats.scrambled,
+                stats.transport_errors,
+                stats.cc_errors,
+                stats.cc_duplicates,
+                stats.cc_discontinuities,
+            );
+        }
+
+        for (check, error) in self.get_errors() {
+            let last = error.last.duration_since(UNIX_EPOCH).unwrap_or_default();
+            result += &format!(
+                "\n    {}: {}, last at {}",
+                check,
+                error.count,
+                last.as_secs(),
+            );
+        }
+
+        result
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::ts::{
+            TS_PACKET_SIZE,
+            TsPacketMut,
+        },
+
+        super::Stats,
+    };
+
+
+    #[test]
+    fn bitrate() {
+        let stats = Stats::default();
+
+        let mut data = Vec::new();
+        for _ in 0 .. 10 {
+            TsPacketMut::push_null(&mut data);
+        }
+
+        stats.push(&data);
+        stats.tick();
+        stats.push(&data[.. 5 * TS_PACKET_SIZE]);
+        stats.tick();
+
+        let pid = &stats.get_pids()[&0x1FFF];
+        assert_eq!(pid.packets, 15);
+        assert_eq!(pid.bitrate(1), 5 * 188 * 8);
+        assert_eq!(pid.bitrate(10), 15 * 188 * 8 / 2);
+        assert_eq!(stats.bitrate(60), 15 * 188 * 8 / 2);
+    }
+
+    #[test]
+    fn bitrate_late_pid() {
+        let stats = Stats::default();
+
+        let mut data = Vec::new();
+        for _ in 0 .. 10 {
+            TsPacketMut::push_null(&mut data);
+        }
+        let mut late = data[.. TS_PACKET_SIZE].to_vec();
+        TsPacketMut::new(&mut late).unwrap().set_pid(0x100);
+
+        for _ in 0 .. 9 {
+            stats.push(&data);
+            stats.tick();
+        }
+        stats.push(&data);
+        stats.push(&late);
+        stats.tick();
+
+        // PID 0x100 appears in the last second of the 10 seconds window
+        let pid = &stats.get_pids()[&0x100];
+        assert_eq!(pid.bitrate(1), 188 * 8);
+        assert_eq!(pid.bitrate(10), 188 * 8 / 10);
+        assert_eq!(stats.bitrate(10), 101 * 188 * 8 / 10);
+    }
+}
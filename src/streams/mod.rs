@@ -11,5 +11,12 @@ pub use udp::UdpStream;
 mod file;
 pub use tokio::fs::File;
 
+mod mux;
+pub use mux::{
+    MuxStream,
+    MuxMessage,
+};
+
 
-pub trait AsyncStream: AsyncRead + AsyncWrite {}
+/// Input or output of the stream. Reads and writes blocks of TS packets.
+pub trait AsyncStream: AsyncRead + AsyncWrite + Send {}
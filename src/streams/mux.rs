@@ -0,0 +1,102 @@
+use {
+    std::{
+        io,
+        pin::Pin,
+        sync::atomic::{
+            AtomicUsize,
+            Ordering,
+        },
+        task::{
+            Poll,
+            Context,
+        },
+    },
+
+    tokio::{
+        io::{
+            ReadBuf,
+            AsyncRead,
+            AsyncWrite,
+        },
+        sync::mpsc::UnboundedSender,
+    },
+
+    super::AsyncStream,
+};
+
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+
+/// Message from the stream to the multiplexer.
+pub enum MuxMessage {
+    /// TS packets from the input with given identifier
+    Data(usize, Vec<u8>),
+    /// Input is closed
+    Close(usize),
+}
+
+
+/// Output stream that sends TS packets to the multiplexer.
+/// Read is not supported and always returns end of stream.
+pub struct MuxStream {
+    id: usize,
+    tx: UnboundedSender<MuxMessage>,
+}
+
+impl MuxStream {
+    /// Creates stream with unique identifier. `tx` is an input of the multiplexer.
+    pub fn new(tx: UnboundedSender<MuxMessage>) -> Self {
+        Self {
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            tx,
+        }
+    }
+}
+
+impl Drop for MuxStream {
+    fn drop(&mut self) {
+        // multiplexer could be already stopped
+        let _ = self.tx.send(MuxMessage::Close(self.id));
+    }
+}
+
+impl AsyncRead for MuxStream {
+    #[inline]
+    fn poll_read(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        _buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for MuxStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, io::Error>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0))
+        }
+
+        match self.tx.send(MuxMessage::Data(self.id, buf.to_vec())) {
+            Ok(()) => Poll::Ready(Ok(buf.len())),
+            Err(_) => Poll::Ready(Err(io::ErrorKind::BrokenPipe.into())),
+        }
+    }
+
+    #[inline]
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    #[inline]
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncStream for MuxStream {}
@@ -25,12 +25,14 @@ use {
 };
 
 
+/// IPv4 UDP socket connected to the remote address.
 pub struct UdpStream {
     inner: UdpSocket,
 }
 
 impl UdpStream {
-    pub async fn new<A: ToSocketAddrs>(addr: A) -> anyhow::Result<Self> {
+    /// Binds socket on the random port and connects it to `addr`.
+    pub async fn new<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
         let inner = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
         inner.connect(addr).await?;
 
@@ -0,0 +1,190 @@
+use super::packet::{
+    TsPacket,
+    TS_SYNC_BYTE,
+    TS_PACKET_SIZE,
+};
+
+
+/// Iterator over TS packets in the byte buffer.
+///
+/// Garbage between packets is skipped. After the sync loss next sync byte is accepted
+/// only if it is followed by another sync byte one packet later. If the buffer ends
+/// before the confirmation could be checked, the iteration stops and the sync byte remains
+/// unconfirmed until the next chunk of the stream is available.
+/// Partial packet at the end of the buffer is not returned and available with
+/// [`remainder`](TsIter::remainder) to be prepended to the next chunk of the stream.
+pub struct TsIter<'a> {
+    buf: &'a [u8],
+    skipped: usize,
+    synced: bool,
+}
+
+impl<'a> TsIter<'a> {
+    /// Creates iterator over packets in the `buf`.
+    /// Buffer is expected to be started with the packet.
+    #[inline]
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self::resume(buf, true)
+    }
+
+    /// Creates iterator over the next chunk of the stream prepended with the remainder
+    /// of the previous one. `synced` is a value of the [`is_synced`](TsIter::is_synced)
+    /// of the previous iterator.
+    #[inline]
+    pub fn resume(buf: &'a [u8], synced: bool) -> Self {
+        Self {
+            buf,
+            skipped: 0,
+            synced,
+        }
+    }
+
+    /// Returns number of bytes skipped to find the sync byte.
+    #[inline]
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+
+    /// Returns `false` if the remainder is started with the sync byte not confirmed yet.
+    #[inline]
+    pub fn is_synced(&self) -> bool {
+        self.synced
+    }
+
+    /// Returns unprocessed tail of the buffer.
+    /// After the end of the iteration it is not longer than the packet and started with
+    /// the sync byte, or empty.
+    #[inline]
+    pub fn remainder(&self) -> &'a [u8] {
+        self.buf
+    }
+
+    /// Skips `n` bytes of the garbage.
+    #[inline]
+    fn skip(&mut self, n: usize) {
+        self.buf = &self.buf[n ..];
+        self.skipped += n;
+    }
+
+    /// Moves to the next sync byte followed by another sync byte one packet later.
+    /// Sync byte is unconfirmed if the buffer ends before the next packet.
+    fn resync(&mut self) {
+        let buf = self.buf;
+        let found = (0 .. buf.len())
+            .filter(|&i| buf[i] == TS_SYNC_BYTE)
+            .find(|&i| buf.get(i + TS_PACKET_SIZE).map_or(true, |&b| b == TS_SYNC_BYTE));
+
+        match found {
+            Some(n) => {
+                self.skip(n);
+                self.synced = self.buf.len() > TS_PACKET_SIZE;
+            }
+            None => {
+                self.skip(buf.len());
+                self.synced = false;
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for TsIter<'a> {
+    type Item = TsPacket<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let &byte = self.buf.first()?;
+        if byte != TS_SYNC_BYTE || ! self.synced {
+            self.resync();
+            if ! self.synced {
+                return None
+            }
+        }
+
+        let ts = TsPacket::new(self.buf).ok()?;
+        self.buf = &self.buf[TS_PACKET_SIZE ..];
+        Some(ts)
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::ts::{
+            TsPacketMut,
+            TS_PACKET_SIZE,
+        },
+
+        super::TsIter,
+    };
+
+
+    fn stream(count: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        for pid in 0 .. count {
+            TsPacketMut::push_null(&mut out).set_pid(pid as u16);
+        }
+        out
+    }
+
+    #[test]
+    fn resync() {
+        let packets = stream(4);
+
+        // garbage with false sync byte before the first packet and between packets
+        let mut data = vec![0x00, 0x47, 0x00];
+        data.extend_from_slice(&packets[.. 2 * TS_PACKET_SIZE]);
+        data.extend_from_slice(&[0xFF; 10]);
+        data.extend_from_slice(&packets[2 * TS_PACKET_SIZE ..]);
+        // partial packet at the end
+        data.extend_from_slice(&packets[.. 100]);
+
+        let mut iter = TsIter::new(&data);
+        let pids: Vec<u16> = (&mut iter).map(|ts| ts.get_pid()).collect();
+        assert_eq!(pids, vec![0, 1, 2, 3]);
+        assert_eq!(iter.skipped(), 13);
+        assert_eq!(iter.remainder(), &packets[.. 100]);
+    }
+
+    #[test]
+    fn chunks() {
+        let data = stream(10);
+
+        let mut pids = Vec::new();
+        let mut tail = Vec::new();
+        let mut synced = true;
+        for chunk in data.chunks(100) {
+            tail.extend_from_slice(chunk);
+            let mut iter = TsIter::resume(&tail, synced);
+            pids.extend((&mut iter).map(|ts| ts.get_pid()));
+            synced = iter.is_synced();
+            let consumed = tail.len() - iter.remainder().len();
+            tail.drain(.. consumed);
+        }
+
+        assert_eq!(pids, (0 .. 10).collect::<Vec<u16>>());
+        assert!(tail.is_empty());
+    }
+
+    #[test]
+    fn unconfirmed() {
+        // false sync byte at the end of the chunk
+        let mut tail = vec![0x00, 0x47, 0x12, 0x34, 0x10, 0x00, 0x00];
+
+        let mut iter = TsIter::new(&tail);
+        assert_eq!(iter.next().map(|ts| ts.get_pid()), None);
+        assert!(! iter.is_synced());
+        assert_eq!(iter.remainder().len(), 6);
+
+        let consumed = tail.len() - iter.remainder().len();
+        tail.drain(.. consumed);
+
+        let packets = stream(0x102);
+        tail.extend_from_slice(&packets[0x100 * TS_PACKET_SIZE ..]);
+
+        let mut iter = TsIter::resume(&tail, false);
+        let pids: Vec<u16> = (&mut iter).map(|ts| ts.get_pid()).collect();
+        assert_eq!(pids, vec![0x100, 0x101]);
+        assert_eq!(iter.skipped(), 6);
+        assert!(iter.is_synced());
+    }
+}
@@ -4,7 +4,37 @@ pub use packet::{
     TS_PACKET_SIZE,
 };
 
+mod packet_mut;
+pub use packet_mut::TsPacketMut;
 
+mod iter;
+pub use iter::TsIter;
+
+mod pcr;
+pub(crate) use pcr::PcrRestamp;
+
+
+/// PID reserved for null packets.
+pub const NULL_PID: u16 = 0x1FFF;
+
+/// 27MHz system clock frequency
+pub const PCR_CLOCK: u64 = 27_000_000;
+/// PCR wraps around after 2^33 * 300 clocks
+pub const PCR_MAX: u64 = (1 << 33) * 300;
+
+
+/// Returns difference between previous PCR and current PCR
+#[inline]
+pub fn pcr_delta(last_pcr: u64, current_pcr: u64) -> u64 {
+    if current_pcr >= last_pcr {
+        current_pcr - last_pcr
+    } else {
+        current_pcr + PCR_MAX - last_pcr
+    }
+}
+
+
+/// Returns true if buffer starts with the TS sync byte
 #[inline]
 pub fn is_sync(ts: &[u8]) -> bool {
     match ts.first() {
@@ -1,39 +1,41 @@
-use {
-    anyhow::{
-        anyhow,
-        Result,
-    },
-
-    super::is_sync,
-};
+use crate::error::Error;
 
 
+/// First byte of each TS packet
 pub const TS_SYNC_BYTE: u8 = 0x47;
+/// Size of the TS packet in bytes
 pub const TS_PACKET_SIZE: usize = 188;
 
 
+/// Zero-copy view of the TS packet.
+/// ISO/IEC 13818-1 : 2.4.3.2 Transport Stream packet layer
 pub struct TsPacket<'a> {
-    ts: &'a [u8],
+    pub(super) ts: &'a [u8],
 }
 
 impl<'a> TsPacket<'a> {
-    pub fn new(ts: &'a [u8]) -> Result<Self> {
-        if ! is_sync(ts) {
-            return Err(
-                anyhow!("ts packet must starts with sync byte {:#x}", TS_SYNC_BYTE)
-            )
+    /// Wraps buffer started with the TS packet. Returns error if buffer is not started
+    /// with the sync byte or shorter than the packet.
+    pub fn new(ts: &'a [u8]) -> Result<Self, Error> {
+        match ts.first() {
+            Some(&TS_SYNC_BYTE) => {}
+            Some(&byte) => return Err(Error::Sync(byte)),
+            None => return Err(Error::Size { expected: TS_PACKET_SIZE, len: 0 }),
         }
 
-        let ts_len = ts.len();
-        if ts_len < TS_PACKET_SIZE {
-            return Err(
-                anyhow!("ts packet must has {} bytes length, got {}", TS_PACKET_SIZE, ts_len)
-            )
+        if ts.len() < TS_PACKET_SIZE {
+            return Err(Error::Size { expected: TS_PACKET_SIZE, len: ts.len() })
         }
 
         Ok(Self { ts: &ts[ .. TS_PACKET_SIZE] })
     }
 
+    /// Returns whole TS packet.
+    #[inline]
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.ts
+    }
+
     /// transport_error_indicator
     ///
     /// ISO/IEC 13818-1
@@ -91,6 +93,24 @@ impl<'a> TsPacket<'a> {
         (u16::from(self.ts[1] & 0x1F) << 8) | u16::from(self.ts[2])
     }
 
+    /// transport_scrambling_control
+    ///
+    /// ISO/IEC 13818-1
+    ///
+    /// This 2-bit field indicates the scrambling mode of the Transport Stream packet payload. The Transport
+    /// Stream packet header, and the adaptation field when present, shall not be scrambled. In the case of a null
+    /// packet the value of the transport_scrambling_control field shall be set to '00'.
+    #[inline]
+    pub fn is_scrambled(&self) -> bool {
+        (self.ts[3] & 0xC0) != 0x00
+    }
+
+    /// Returns 2-bit transport_scrambling_control value.
+    #[inline]
+    pub fn get_scrambling(&self) -> u8 {
+        self.ts[3] >> 6
+    }
+
     /// adaptation_field_control
     ///
     /// ISO/IEC 13818-1
@@ -106,6 +126,8 @@ impl<'a> TsPacket<'a> {
         (self.ts[3] & 0x20) != 0x00
     }
 
+    /// Returns true if packet contains payload.
+    /// adaptation_field_control - '01' or '11'
     #[inline]
     pub fn is_payload(&self) -> bool {
         (self.ts[3] & 0x10) != 0x00
@@ -158,6 +180,73 @@ impl<'a> TsPacket<'a> {
         self.ts[4]
     }
 
+    /// discontinuity_indicator
+    ///
+    /// ISO/IEC 13818-1
+    ///
+    /// This is a 1-bit field which when set to '1' indicates that the discontinuity state is true for the
+    /// current Transport Stream packet. When the discontinuity_indicator is set to '0' or is not present, the
+    /// discontinuity state is false. The discontinuity indicator is used to indicate two types of
+    /// discontinuities, system time-base discontinuities and continuity_counter discontinuities.
+    #[inline]
+    pub fn is_discontinuity(&self) -> bool {
+        self.is_adaptation() && self.get_adaptation_size() > 0 && (self.ts[5] & 0x80) != 0x00
+    }
+
+    /// random_access_indicator
+    ///
+    /// ISO/IEC 13818-1
+    ///
+    /// The random_access_indicator is a 1-bit field that indicates that the current Transport Stream packet, and
+    /// possibly subsequent Transport Stream packets with the same PID, contain some information to aid random
+    /// access at this point.
+    #[inline]
+    pub fn is_random_access(&self) -> bool {
+        self.is_adaptation() && self.get_adaptation_size() > 0 && (self.ts[5] & 0x40) != 0x00
+    }
+
+    /// PCR_flag
+    ///
+    /// ISO/IEC 13818-1
+    ///
+    /// The PCR_flag is a 1-bit flag. A value of '1' indicates that the adaptation_field contains a PCR field
+    /// coded in two parts. A value of '0' indicates that the adaptation field does not contain any PCR field.
+    #[inline]
+    pub fn is_pcr(&self) -> bool {
+        self.is_adaptation() && self.get_adaptation_size() >= 7 && (self.ts[5] & 0x10) != 0x00
+    }
+
+    /// program_clock_reference
+    ///
+    /// ISO/IEC 13818-1
+    ///
+    /// The program_clock_reference (PCR) is a 42-bit field coded in two parts. The first part,
+    /// program_clock_reference_base, is a 33-bit field whose value is given by PCR_base(i). The second part,
+    /// program_clock_reference_extension, is a 9-bit field whose value is given by PCR_ext(i). The PCR indicates
+    /// the intended time of arrival of the byte containing the last bit of the program_clock_reference_base at
+    /// the input of the system target decoder.
+    ///
+    /// Returns PCR in 27MHz clocks: `PCR_base * 300 + PCR_ext`
+    #[inline]
+    pub fn get_pcr(&self) -> Option<u64> {
+        if ! self.is_pcr() {
+            return None
+        }
+
+        let pcr = &self.ts[6 .. 12];
+        let base =
+            (u64::from(pcr[0]) << 25) |
+            (u64::from(pcr[1]) << 17) |
+            (u64::from(pcr[2]) <<  9) |
+            (u64::from(pcr[3]) <<  1) |
+            (u64::from(pcr[4]) >>  7);
+        let ext =
+            (u64::from(pcr[4] & 0x01) << 8) |
+            (u64::from(pcr[5]));
+
+        Some(base * 300 + ext)
+    }
+
     /// Packet payload offset calculation.
     #[inline]
     pub fn get_payload_offset(&self) -> u8 {
@@ -174,6 +263,7 @@ impl<'a> TsPacket<'a> {
         self.ts[self.get_payload_offset() as usize .. ].as_ref()
     }
 
+    /// Returns true if payload starts with the PES packet_start_code_prefix
     #[inline]
     pub fn is_pes(&self) -> bool {
         let payload = self.get_payload();
@@ -183,8 +273,13 @@ impl<'a> TsPacket<'a> {
 
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod test {
-    use super::TsPacket;
+    use super::{
+        Error,
+        TsPacket,
+        TS_PACKET_SIZE,
+    };
 
 
     // TS packet that doesn't start with sync byte.
@@ -244,10 +339,10 @@ mod test {
     #[test]
     fn new() {
         let not_sync = TsPacket::new(NOT_SYNC_PACKET);
-        assert_eq!(not_sync.is_err(), true);
+        assert!(matches!(not_sync, Err(Error::Sync(_))));
 
         let short = TsPacket::new(SHORT_PACKET);
-        assert_eq!(short.is_err(), true);
+        assert!(matches!(short, Err(Error::Size { expected: TS_PACKET_SIZE, .. })));
 
         let ts = TsPacket::new(PACKET);
         assert_eq!(ts.is_ok(), true)
@@ -0,0 +1,369 @@
+use {
+    crate::error::Error,
+
+    super::{
+        packet::TS_SYNC_BYTE,
+        TsPacket,
+        TS_PACKET_SIZE,
+        NULL_PID,
+    },
+};
+
+
+/// Size of the packet after the 4-byte header
+const TS_BODY_SIZE: usize = TS_PACKET_SIZE - 4;
+
+
+/// Mutable view of the TS packet.
+///
+/// Header fields are changed in place. Adaptation field and payload are laid out
+/// on each change so the packet remains valid: payload is aligned to the end of the packet
+/// and the gap is filled with adaptation field stuffing. Adaptation field has priority,
+/// growing it truncates the payload, so the payload should be set last.
+pub struct TsPacketMut<'a> {
+    ts: &'a mut [u8],
+}
+
+impl<'a> TsPacketMut<'a> {
+    /// Wraps buffer started with the TS packet. Returns error if buffer is not started
+    /// with the sync byte or shorter than the packet.
+    pub fn new(ts: &'a mut [u8]) -> Result<Self, Error> {
+        TsPacket::new(ts)?;
+        Ok(Self { ts: &mut ts[.. TS_PACKET_SIZE] })
+    }
+
+    /// Appends empty packet to the `out`: null PID, adaptation field with stuffing only, no payload.
+    pub fn push(out: &mut Vec<u8>) -> TsPacketMut<'_> {
+        let start = out.len();
+        out.extend_from_slice(&[TS_SYNC_BYTE, 0x00, 0x00, 0x20, 183, 0x00]);
+        out.resize(start + TS_PACKET_SIZE, 0xFF);
+
+        let mut packet = TsPacketMut { ts: &mut out[start ..] };
+        packet.set_pid(NULL_PID);
+        packet
+    }
+
+    /// Appends null packet to the `out`: payload with stuffing bytes, no adaptation field.
+    pub fn push_null(out: &mut Vec<u8>) -> TsPacketMut<'_> {
+        let start = out.len();
+        out.extend_from_slice(&[TS_SYNC_BYTE, 0x1F, 0xFF, 0x10]);
+        out.resize(start + TS_PACKET_SIZE, 0xFF);
+        TsPacketMut { ts: &mut out[start ..] }
+    }
+
+    /// Appends copy of the packet to the `out`.
+    pub fn push_packet<'b>(out: &'b mut Vec<u8>, ts: &TsPacket) -> TsPacketMut<'b> {
+        let start = out.len();
+        out.extend_from_slice(ts.as_bytes());
+        TsPacketMut { ts: &mut out[start ..] }
+    }
+
+    /// Returns read-only view of the packet.
+    #[inline]
+    pub fn as_packet(&self) -> TsPacket<'_> {
+        TsPacket { ts: self.ts }
+    }
+
+    /// Returns whole TS packet.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.ts
+    }
+
+    #[inline]
+    fn set_bit(byte: &mut u8, mask: u8, value: bool) {
+        if value {
+            *byte |= mask;
+        } else {
+            *byte &= ! mask;
+        }
+    }
+
+    /// Sets transport_error_indicator.
+    #[inline]
+    pub fn set_error(&mut self, value: bool) {
+        Self::set_bit(&mut self.ts[1], 0x80, value);
+    }
+
+    /// Sets payload_unit_start_indicator.
+    #[inline]
+    pub fn set_pusi(&mut self, value: bool) {
+        Self::set_bit(&mut self.ts[1], 0x40, value);
+    }
+
+    /// Sets 13-bit PID.
+    #[inline]
+    pub fn set_pid(&mut self, pid: u16) {
+        self.ts[1] = (self.ts[1] & 0xE0) | ((pid >> 8) as u8 & 0x1F);
+        self.ts[2] = pid as u8;
+    }
+
+    /// Sets 2-bit transport_scrambling_control.
+    #[inline]
+    pub fn set_scrambling(&mut self, value: u8) {
+        self.ts[3] = (self.ts[3] & 0x3F) | ((value & 0x03) << 6);
+    }
+
+    /// Sets 4-bit continuity_counter.
+    #[inline]
+    pub fn set_cc(&mut self, cc: u8) {
+        self.ts[3] = (self.ts[3] & 0xF0) | (cc & 0x0F);
+    }
+
+    /// Sets discontinuity_indicator in the adaptation field.
+    pub fn set_discontinuity(&mut self, value: bool) {
+        self.set_flag(0x80, value);
+    }
+
+    /// Sets random_access_indicator in the adaptation field.
+    pub fn set_random_access(&mut self, value: bool) {
+        self.set_flag(0x40, value);
+    }
+
+    /// Sets PCR in 27MHz clocks or removes PCR field from the adaptation field.
+    pub fn set_pcr(&mut self, pcr: Option<u64>) {
+        let mut adaptation = self.adaptation();
+        let has_pcr = adaptation.len() >= 7 && adaptation[0] & 0x10 != 0;
+
+        match (pcr, has_pcr) {
+            (Some(pcr), true) => {
+                self.write_pcr(pcr);
+                return
+            }
+            (Some(_), false) => {
+                if adaptation.is_empty() {
+                    adaptation.push(0x00);
+                }
+                adaptation[0] |= 0x10;
+                adaptation.splice(1 .. 1, [0x00; 6]);
+            }
+            (None, true) => {
+                adaptation[0] &= ! 0x10;
+                adaptation.drain(1 .. 7);
+            }
+            (None, false) => return,
+        }
+
+        let payload = self.payload();
+        self.layout(&adaptation, &payload);
+
+        if let Some(pcr) = pcr {
+            self.write_pcr(pcr);
+        }
+    }
+
+    /// Writes PCR value to the PCR field. Packet should have PCR field.
+    fn write_pcr(&mut self, pcr: u64) {
+        let base = (pcr / 300) % (1 << 33);
+        let ext = pcr % 300;
+
+        self.ts[6] = (base >> 25) as u8;
+        self.ts[7] = (base >> 17) as u8;
+        self.ts[8] = (base >> 9) as u8;
+        self.ts[9] = (base >> 1) as u8;
+        self.ts[10] = (((base & 0x01) as u8) << 7) | 0x7E | ((ext >> 8) as u8 & 0x01);
+        self.ts[11] = ext as u8;
+    }
+
+    /// Sets packet payload. If data is shorter than available space, adaptation field is
+    /// extended with stuffing bytes. Returns number of bytes written.
+    pub fn set_payload(&mut self, data: &[u8]) -> usize {
+        let adaptation = self.adaptation();
+        self.layout(&adaptation, data)
+    }
+
+    fn set_flag(&mut self, mask: u8, value: bool) {
+        let mut adaptation = self.adaptation();
+        if adaptation.is_empty() {
+            if ! value {
+                return
+            }
+            adaptation.push(0x00);
+        }
+
+        Self::set_bit(&mut adaptation[0], mask, value);
+
+        let payload = self.payload();
+        self.layout(&adaptation, &payload);
+    }
+
+    /// Returns payload of the packet.
+    fn payload(&self) -> Vec<u8> {
+        let packet = self.as_packet();
+        if packet.is_payload() {
+            packet.get_payload().to_vec()
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Returns adaptation field without length and stuffing: flags and optional fields.
+    fn adaptation(&self) -> Vec<u8> {
+        let packet = self.as_packet();
+        if ! packet.is_adaptation() || packet.get_adaptation_size() == 0 {
+            return Vec::new()
+        }
+
+        let field = &self.ts[5 .. 5 + usize::from(packet.get_adaptation_size()).min(TS_BODY_SIZE - 1)];
+        let flags = field[0];
+
+        // PCR, OPCR, splice_countdown
+        let mut size = 1;
+        for (mask, len) in [(0x10, 6), (0x08, 6), (0x04, 1)] {
+            if flags & mask != 0 {
+                size += len;
+            }
+        }
+        // transport_private_data and adaptation_field_extension with the length byte
+        for mask in [0x02, 0x01] {
+            if flags & mask != 0 {
+                size += 1 + field.get(size).map_or(0, |&len| usize::from(len));
+            }
+        }
+
+        field[.. size.min(field.len())].to_vec()
+    }
+
+    /// Writes adaptation field and payload with stuffing between them.
+    /// Returns number of the payload bytes written.
+    fn layout(&mut self, adaptation: &[u8], payload: &[u8]) -> usize {
+        // adaptation field with flags only is not required
+        let adaptation = if adaptation == [0x00] { &[][..] } else { adaptation };
+        let adaptation = &adaptation[.. adaptation.len().min(TS_BODY_SIZE - 1)];
+
+        self.ts[3] &= 0xCF;
+
+        if adaptation.is_empty() && payload.len() >= TS_BODY_SIZE {
+            self.ts[3] |= 0x10;
+            self.ts[4 ..].copy_from_slice(&payload[.. TS_BODY_SIZE]);
+            return TS_BODY_SIZE
+        }
+
+        let size = payload.len().min(TS_BODY_SIZE - 1 - adaptation.len());
+        let adaptation_size = TS_BODY_SIZE - 1 - size;
+
+        self.ts[3] |= if size > 0 { 0x30 } else { 0x20 };
+        self.ts[4] = adaptation_size as u8;
+
+        let field = &mut self.ts[5 .. 5 + adaptation_size];
+        field.fill(0xFF);
+        if let Some(flags) = field.first_mut() {
+            *flags = 0x00;
+        }
+        field[.. adaptation.len()].copy_from_slice(adaptation);
+
+        self.ts[5 + adaptation_size ..].copy_from_slice(&payload[.. size]);
+        size
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::ts::{
+            TsPacket,
+            TS_PACKET_SIZE,
+            NULL_PID,
+        },
+
+        super::TsPacketMut,
+    };
+
+
+    #[test]
+    fn header() {
+        let mut out = Vec::new();
+        let mut packet = TsPacketMut::push(&mut out);
+        assert_eq!(packet.as_packet().get_pid(), NULL_PID);
+
+        packet.set_pid(0x1234);
+        packet.set_cc(0x1F);
+        packet.set_pusi(true);
+        packet.set_error(true);
+        packet.set_scrambling(0x03);
+        packet.set_scrambling(0x02);
+
+        assert_eq!(out.len(), TS_PACKET_SIZE);
+        let ts = TsPacket::new(&out).unwrap();
+        assert_eq!(ts.get_pid(), 0x1234);
+        assert_eq!(ts.get_cc(), 0x0F);
+        assert!(ts.is_pusi());
+        assert!(ts.is_error());
+        assert_eq!(ts.get_scrambling(), 0x02);
+        assert!(! ts.is_payload());
+        assert!(ts.get_payload().is_empty());
+    }
+
+    #[test]
+    fn adaptation() {
+        let data: Vec<u8> = (0 .. 200).map(|i| i as u8).collect();
+
+        let mut out = Vec::new();
+        for size in [0, 1, 10, 176, 183, 184, 200] {
+            let mut packet = TsPacketMut::push(&mut out);
+            packet.set_pid(0x100);
+            assert_eq!(packet.set_payload(&data[.. size]), size.min(184));
+
+            let ts = packet.as_packet();
+            assert_eq!(ts.get_payload(), &data[.. size.min(184)]);
+            assert_eq!(ts.is_adaptation(), size < 184);
+            assert_eq!(ts.get_pcr(), None);
+        }
+
+        // adaptation field is added before the payload, payload is truncated
+        let mut packet = TsPacketMut::new(&mut out[.. TS_PACKET_SIZE]).unwrap();
+        packet.set_payload(&data);
+        packet.set_random_access(true);
+        packet.set_pcr(Some(1_000_000));
+        packet.set_discontinuity(true);
+
+        let ts = packet.as_packet();
+        assert_eq!(ts.get_pcr(), Some(1_000_000));
+        assert!(ts.is_discontinuity());
+        assert!(ts.is_random_access());
+        assert_eq!(ts.get_payload(), &data[.. 176]);
+
+        // PCR is updated in place
+        packet.set_pcr(Some(2_000_000));
+        assert_eq!(packet.as_packet().get_pcr(), Some(2_000_000));
+        assert_eq!(packet.as_packet().get_payload(), &data[.. 176]);
+
+        // payload is kept when adaptation field shrinks
+        packet.set_pcr(None);
+        packet.set_discontinuity(false);
+        packet.set_random_access(false);
+
+        let ts = packet.as_packet();
+        assert_eq!(ts.get_pcr(), None);
+        assert!(! ts.is_discontinuity());
+        assert_eq!(ts.get_adaptation_size(), 7);
+        assert_eq!(ts.get_payload(), &data[.. 176]);
+
+        packet.set_payload(&data);
+        assert!(! packet.as_packet().is_adaptation());
+    }
+
+    #[test]
+    fn push() {
+        let mut out = Vec::new();
+        TsPacketMut::push_null(&mut out);
+
+        let ts = TsPacket::new(&out).unwrap();
+        assert_eq!(ts.get_pid(), NULL_PID);
+        assert!(! ts.is_adaptation());
+        assert_eq!(ts.get_payload(), &[0xFF; 184][..]);
+
+        let copy = out.clone();
+        let mut packet = TsPacketMut::push_packet(&mut out, &TsPacket::new(&copy).unwrap());
+        packet.set_pid(0x100);
+        // PCR field is added to the packet without adaptation field
+        packet.set_pcr(Some(1_000_000));
+
+        assert_eq!(out.len(), 2 * TS_PACKET_SIZE);
+        let ts = TsPacket::new(&out[TS_PACKET_SIZE ..]).unwrap();
+        assert_eq!(ts.get_pid(), 0x100);
+        assert_eq!(ts.get_pcr(), Some(1_000_000));
+        assert_eq!(ts.get_payload(), &[0xFF; 176][..]);
+    }
+}
@@ -0,0 +1,163 @@
+use {
+    std::collections::HashMap,
+
+    super::{
+        TsPacketMut,
+        TS_PACKET_SIZE,
+        PCR_CLOCK,
+        PCR_MAX,
+    },
+};
+
+
+/// Maximum PCR correction per PCR packet in 27MHz clocks (~370ns).
+/// Keeps PCR accuracy within ±500ns defined by ISO/IEC 13818-1
+const MAX_STEP: i64 = 10;
+/// Error above this value is handled as discontinuity
+const MAX_ERROR: i64 = PCR_CLOCK as i64;
+
+
+/// Returns signed difference between two PCR values with wrap around
+#[inline]
+fn pcr_diff(a: u64, b: u64) -> i64 {
+    let diff = (a + PCR_MAX - b) % PCR_MAX;
+    if diff > PCR_MAX / 2 {
+        diff as i64 - PCR_MAX as i64
+    } else {
+        diff as i64
+    }
+}
+
+
+#[derive(Default)]
+struct PcrState {
+    /// Difference between source PCR and output time
+    offset: i64,
+    /// Smoothed difference between source PCR and restamped PCR
+    error: i64,
+}
+
+
+/// PCR restamping engine for the constant bitrate output.
+/// Used by the stuffing stage and the multiplexer, which know position of each output packet.
+///
+/// PCR is calculated from the packet position in the output and the output bitrate.
+/// Each PCR PID has own offset from the output time. Offset slowly follows the source PCR
+/// to compensate clock drift without exceeding PCR accuracy limits.
+pub struct PcrRestamp {
+    bitrate: u64,
+    /// Number of the output packets
+    position: u64,
+    pids: HashMap<u16, PcrState>,
+}
+
+impl PcrRestamp {
+    /// `bitrate` is an output bitrate in bits per second.
+    pub fn new(bitrate: u64) -> Self {
+        Self {
+            bitrate,
+            position: 0,
+            pids: HashMap::new(),
+        }
+    }
+
+    /// Returns output time in 27MHz clocks of the packet at given position
+    #[inline]
+    fn position_to_time(&self, position: u64) -> u64 {
+        let time = u128::from(position) * u128::from(PCR_CLOCK) * (TS_PACKET_SIZE as u128 * 8)
+            / u128::from(self.bitrate);
+        (time % u128::from(PCR_MAX)) as u64
+    }
+
+    /// Restamps PCR in the output packet. Should be called for each output packet in order.
+    pub fn restamp(&mut self, ts: &mut [u8]) {
+        let position = self.position;
+        self.position += 1;
+
+        let mut packet = match TsPacketMut::new(ts) {
+            Ok(v) => v,
+            Err(_) => return,
+        };
+        let view = packet.as_packet();
+        let (pid, pcr, discontinuity) = match view.get_pcr() {
+            Some(pcr) => (view.get_pid(), pcr, view.is_discontinuity()),
+            None => return,
+        };
+
+        let time = self.position_to_time(position);
+        let offset = pcr_diff(pcr, time);
+
+        let state = self.pids.entry(pid).or_insert_with(|| PcrState {
+            offset,
+            error: 0,
+        });
+
+        let error = offset - state.offset;
+        if discontinuity || error.abs() > MAX_ERROR {
+            state.offset = offset;
+            state.error = 0;
+        } else {
+            state.error += (error - state.error) / 16;
+            state.offset += state.error.clamp(-MAX_STEP, MAX_STEP);
+        }
+
+        let pcr = (time as i64 + state.offset).rem_euclid(PCR_MAX as i64) as u64;
+        packet.set_pcr(Some(pcr));
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use {
+        crate::ts::{
+            TsPacket,
+            TsPacketMut,
+            TS_PACKET_SIZE,
+        },
+
+        super::PcrRestamp,
+    };
+
+
+    fn packet(pcr: u64) -> Vec<u8> {
+        let mut ts = vec![0x47, 0x01, 0x00, 0x30, 7, 0x10];
+        ts.resize(TS_PACKET_SIZE, 0xFF);
+        TsPacketMut::new(&mut ts).unwrap().set_pcr(Some(pcr));
+        ts
+    }
+
+    #[test]
+    fn restamp() {
+        // 1 packet per millisecond
+        let bitrate = (TS_PACKET_SIZE * 8 * 1000) as u64;
+        let mut restamp = PcrRestamp::new(bitrate);
+
+        let mut ts = packet(1_000_000);
+        restamp.restamp(&mut ts);
+        assert_eq!(TsPacket::new(&ts).unwrap().get_pcr(), Some(1_000_000));
+
+        let mut ts = vec![0x47, 0x01, 0x00, 0x10];
+        ts.resize(TS_PACKET_SIZE, 0xFF);
+        restamp.restamp(&mut ts);
+
+        // source PCR is 1ms ahead of the output position.
+        // PCR follows the source not more than maximum step
+        let mut ts = packet(1_000_000 + 27_000 * 3);
+        restamp.restamp(&mut ts);
+        assert_eq!(TsPacket::new(&ts).unwrap().get_pcr(), Some(1_000_000 + 27_000 * 2 + 10));
+    }
+
+    #[test]
+    fn discontinuity() {
+        let bitrate = (TS_PACKET_SIZE * 8 * 1000) as u64;
+        let mut restamp = PcrRestamp::new(bitrate);
+
+        let mut ts = packet(1_000_000);
+        restamp.restamp(&mut ts);
+
+        let mut ts = packet(500_000_000);
+        restamp.restamp(&mut ts);
+        assert_eq!(TsPacket::new(&ts).unwrap().get_pcr(), Some(500_000_000));
+    }
+}